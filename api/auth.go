@@ -0,0 +1,187 @@
+package main
+
+import (
+	"crypto/subtle"
+	"net/http"
+	"os"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"github.com/google/uuid"
+)
+
+// Setting API_PASSWORD makes every route require a token from /login.
+// Tokens last for TOKEN_LIFETIME or until the API restarts.
+var API_USER = os.Getenv("API_USER")
+var API_PASSWORD = os.Getenv("API_PASSWORD")
+
+const TOKEN_LIFETIME = 24 * time.Hour
+
+// Cookie /login sets for media elements, EventSource and WebSocket
+// clients, which can't set headers
+const TOKEN_COOKIE = "access_token"
+
+// Token a request carries as a bearer token or, on the routes clients
+// that can't set headers use, as TOKEN_COOKIE. The cookie isn't taken
+// anywhere else so other sites can't make changes with it.
+func token_of(r *http.Request) string {
+	if header := r.Header.Get("Authorization"); strings.HasPrefix(header, "Bearer ") {
+		return strings.TrimPrefix(header, "Bearer ")
+	}
+	p := r.URL.Path
+	if p == "/auth" || p == "/ws" || strings.HasPrefix(p, "/events/") {
+		if cookie, err := r.Cookie(TOKEN_COOKIE); err == nil {
+			return cookie.Value
+		}
+	}
+	return ""
+}
+
+// Longest a client has to wait after failing to log in
+const MAX_LOGIN_DELAY = time.Minute
+
+// Failed logins from one address in a row, and until when it has to wait
+type failed_logins struct {
+	count int
+	until time.Time
+}
+
+// Delays the next login from an address after each failed one, doubling
+// the delay up to MAX_LOGIN_DELAY so passwords can't be guessed quickly
+type login_throttle struct {
+	mu    sync.Mutex
+	by_ip map[string]failed_logins
+}
+
+func new_login_throttle() *login_throttle {
+	return &login_throttle{by_ip: make(map[string]failed_logins)}
+}
+
+// How long `ip` has yet to wait before it may try again
+func (t *login_throttle) wait(ip string, now time.Time) time.Duration {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	return max(t.by_ip[ip].until.Sub(now), 0)
+}
+
+func (t *login_throttle) failed(ip string, now time.Time) {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	// addresses that stayed quiet for a while start over
+	for other, f := range t.by_ip {
+		if now.After(f.until.Add(MAX_LOGIN_DELAY)) {
+			delete(t.by_ip, other)
+		}
+	}
+	f := t.by_ip[ip]
+	f.count++
+	f.until = now.Add(min(time.Second<<min(f.count-1, 6), MAX_LOGIN_DELAY))
+	t.by_ip[ip] = f
+}
+
+func (t *login_throttle) succeeded(ip string) {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	delete(t.by_ip, ip)
+}
+
+// Register /login and /auth and require a token on every route
+// registered after it. Without API_PASSWORD everything is allowed.
+func add_auth(router *gin.Engine) {
+	if API_PASSWORD == "" {
+		router.GET("/auth", func(c *gin.Context) { c.Status(http.StatusNoContent) })
+		return
+	}
+	user := API_USER
+	if user == "" {
+		user = "admin"
+	}
+	// expiry by token
+	tokens := make(map[string]time.Time)
+	mu := sync.Mutex{}
+	valid := func(token string) bool {
+		mu.Lock()
+		defer mu.Unlock()
+		expiry, ok := tokens[token]
+		if ok && time.Now().After(expiry) {
+			delete(tokens, token)
+			return false
+		}
+		return ok
+	}
+	throttle := new_login_throttle()
+
+	router.Use(func(c *gin.Context) {
+		switch c.Request.URL.Path {
+		case "/login", "/auth":
+			c.Next()
+		default:
+			if !valid(token_of(c.Request)) {
+				c.String(http.StatusUnauthorized, "login required")
+				c.Abort()
+			}
+		}
+	})
+
+	router.POST("/login", func(c *gin.Context) {
+		ip := c.ClientIP()
+		if wait := throttle.wait(ip, time.Now()); wait > 0 {
+			seconds := int((wait + time.Second - 1) / time.Second)
+			c.Header("Retry-After", strconv.Itoa(seconds))
+			c.String(http.StatusTooManyRequests, "too many failed logins, try again in %ds", seconds)
+			return
+		}
+		var credentials struct {
+			Username string `json:"username"`
+			Password string `json:"password"`
+		}
+		if err := c.BindJSON(&credentials); err != nil {
+			c.String(http.StatusBadRequest, "invalid JSON: %s", err)
+			return
+		}
+		user_ok := subtle.ConstantTimeCompare([]byte(credentials.Username), []byte(user)) == 1
+		password_ok := subtle.ConstantTimeCompare([]byte(credentials.Password), []byte(API_PASSWORD)) == 1
+		if !user_ok || !password_ok {
+			throttle.failed(ip, time.Now())
+			c.String(http.StatusUnauthorized, "wrong username or password")
+			return
+		}
+		throttle.succeeded(ip)
+		token := uuid.NewString()
+		now := time.Now()
+		mu.Lock()
+		// tokens that were never used again would pile up otherwise
+		for t, expiry := range tokens {
+			if now.After(expiry) {
+				delete(tokens, t)
+			}
+		}
+		tokens[token] = now.Add(TOKEN_LIFETIME)
+		mu.Unlock()
+		// strict, so pages on other sites can't open a WebSocket with it
+		http.SetCookie(c.Writer, &http.Cookie{
+			Name:     TOKEN_COOKIE,
+			Value:    token,
+			Path:     "/",
+			MaxAge:   int(TOKEN_LIFETIME.Seconds()),
+			Secure:   c.Request.TLS != nil || c.GetHeader("X-Forwarded-Proto") == "https",
+			HttpOnly: true,
+			SameSite: http.SameSiteStrictMode,
+		})
+		c.JSON(http.StatusOK, gin.H{"token": token})
+	})
+
+	// Subrequest nginx makes before passing uploads on to tusd and serving
+	// media files, with the original request's headers and cookies. CORS
+	// preflight requests don't carry credentials and are let through.
+	router.GET("/auth", func(c *gin.Context) {
+		if c.GetHeader("X-Original-Method") == http.MethodOptions || valid(token_of(c.Request)) {
+			c.Status(http.StatusNoContent)
+		} else {
+			c.Status(http.StatusUnauthorized)
+		}
+	})
+}
@@ -0,0 +1,94 @@
+package main
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+	"time"
+)
+
+// Require a login with `password` until the test ends
+func require_password(t *testing.T, password string) {
+	previous := API_PASSWORD
+	API_PASSWORD = password
+	t.Cleanup(func() { API_PASSWORD = previous })
+}
+
+// Log in to `server` as admin with `password`
+func login(t *testing.T, server *httptest.Server, password string) *http.Response {
+	t.Helper()
+	body := strings.NewReader(`{"username": "admin", "password": "` + password + `"}`)
+	res, err := http.Post(server.URL+"/login", "application/json", body)
+	if err != nil {
+		t.Fatal(err)
+	}
+	res.Body.Close()
+	return res
+}
+
+func TestCookieOnlyOpensEventsAndMedia(t *testing.T) {
+	require_password(t, "secret")
+	server := test_server(t, NewMemCollection(), new_event_hub())
+	cookies := login(t, server, "secret").Cookies()
+
+	for path, status := range map[string]int{
+		"/auth":  http.StatusNoContent,
+		"/media": http.StatusUnauthorized,
+	} {
+		req, _ := http.NewRequest("GET", server.URL+path, nil)
+		for _, cookie := range cookies {
+			req.AddCookie(cookie)
+		}
+		res, err := http.DefaultClient.Do(req)
+		if err != nil {
+			t.Fatal(err)
+		}
+		res.Body.Close()
+		if res.StatusCode != status {
+			t.Errorf("%s: expected %d, got %d", path, status, res.StatusCode)
+		}
+	}
+}
+
+func TestLoginThrottleDoublesTheDelay(t *testing.T) {
+	throttle := new_login_throttle()
+	now := time.Now()
+	for i, delay := range []time.Duration{time.Second, 2 * time.Second, 4 * time.Second} {
+		throttle.failed("a", now)
+		if wait := throttle.wait("a", now); wait != delay {
+			t.Fatalf("after %d failures: expected to wait %v, got %v", i+1, delay, wait)
+		}
+	}
+	if wait := throttle.wait("b", now); wait != 0 {
+		t.Fatalf("other addresses shouldn't wait, got %v", wait)
+	}
+	for i := 0; i < 100; i++ {
+		throttle.failed("a", now)
+	}
+	if wait := throttle.wait("a", now); wait != MAX_LOGIN_DELAY {
+		t.Fatalf("expected to wait at most %v, got %v", MAX_LOGIN_DELAY, wait)
+	}
+	throttle.succeeded("a")
+	if wait := throttle.wait("a", now); wait != 0 {
+		t.Fatalf("expected to start over after logging in, got %v", wait)
+	}
+}
+
+func TestFailedLoginsAreThrottled(t *testing.T) {
+	require_password(t, "secret")
+	server := test_server(t, NewMemCollection(), new_event_hub())
+
+	if res := login(t, server, "guess"); res.StatusCode != http.StatusUnauthorized {
+		t.Fatalf("expected a wrong password to be rejected, got %d", res.StatusCode)
+	}
+	// even the right password has to wait
+	res := login(t, server, "secret")
+	if res.StatusCode != http.StatusTooManyRequests || res.Header.Get("Retry-After") != "1" {
+		t.Fatalf("expected to be told to wait a second, got %d %v", res.StatusCode, res.Header)
+	}
+	time.Sleep(time.Second)
+	if res := login(t, server, "secret"); res.StatusCode != http.StatusOK {
+		t.Fatalf("expected to log in after waiting, got %d", res.StatusCode)
+	}
+}
@@ -0,0 +1,284 @@
+package main
+
+import (
+	"bytes"
+	"fmt"
+	"io"
+	"log"
+	"net/http"
+	"path"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/gin-contrib/sse"
+	"github.com/gin-gonic/gin"
+	"golang.org/x/net/websocket"
+)
+
+// Number of past events kept for clients that reconnect
+const HISTORY_SIZE = 256
+
+// Events queued for a client before it counts as too slow
+const CLIENT_BUFFER = 64
+
+// A server-sent event along with the id clients resume from
+type event struct {
+	id   string
+	data gin.H
+}
+
+func (ev event) render(c *gin.Context) {
+	c.Render(-1, sse.Event{Id: ev.id, Event: "message", Data: ev.data})
+}
+
+// Media events, the clients listening for them and the recent history
+// clients catch up from when they reconnect
+type event_hub struct {
+	mu      sync.Mutex
+	clients map[chan event]struct{}
+	// Event ids are `<epoch>-<seq>` so that ids handed out before a
+	// restart are never mistaken for current ones.
+	epoch   string
+	seq     int
+	history []event
+}
+
+func new_event_hub() *event_hub {
+	return &event_hub{
+		clients: make(map[chan event]struct{}),
+		epoch:   strconv.FormatInt(time.Now().UnixNano(), 36),
+	}
+}
+
+// Must be called with `mu` held
+func (hub *event_hub) current_id() string {
+	return fmt.Sprintf("%s-%d", hub.epoch, hub.seq)
+}
+
+// Send `data` to every client. Events are tagged with `type`: created,
+// updated, removed or resync. Clients that can't keep up are
+// disconnected rather than block the server. They catch up on what they
+// missed when they reconnect.
+func (hub *event_hub) broadcast(data gin.H) {
+	hub.mu.Lock()
+	defer hub.mu.Unlock()
+	hub.seq++
+	ev := event{id: hub.current_id(), data: data}
+	hub.history = append(hub.history, ev)
+	if len(hub.history) > HISTORY_SIZE {
+		hub.history = hub.history[1:]
+	}
+	log.Printf("clients: %d", len(hub.clients))
+	for client := range hub.clients {
+		select {
+		case client <- ev:
+		default:
+			log.Printf("client too slow, disconnecting it")
+			close(client)
+			delete(hub.clients, client)
+		}
+	}
+}
+
+// Events a client missed since `last_id`, or a single resync event if
+// they are no longer known. Must be called with `mu` held.
+func (hub *event_hub) missed(last_id string) []event {
+	if last_id == "" || last_id == hub.current_id() {
+		return nil
+	}
+	for i, ev := range hub.history {
+		if ev.id == last_id {
+			return append([]event{}, hub.history[i+1:]...)
+		}
+	}
+	return []event{{id: hub.current_id(), data: gin.H{"type": "resync"}}}
+}
+
+// Register a client for events after `last_id`. Returns the channel new
+// events arrive on, the events it missed and a function to call once the
+// client is gone. The channel is closed when the client falls behind.
+func (hub *event_hub) subscribe(last_id string) (chan event, []event, func()) {
+	ch := make(chan event, CLIENT_BUFFER)
+	log.Printf("new client")
+	hub.mu.Lock()
+	defer hub.mu.Unlock()
+	hub.clients[ch] = struct{}{}
+	unsubscribe := func() {
+		hub.mu.Lock()
+		defer hub.mu.Unlock()
+		// slow clients are closed by `broadcast`
+		if _, ok := hub.clients[ch]; ok {
+			log.Printf("closing client channel")
+			close(ch)
+			delete(hub.clients, ch)
+		}
+	}
+	return ch, hub.missed(last_id), unsubscribe
+}
+
+// Register the routes clients listen for events on: server-sent events
+// at /events/media and a WebSocket at /ws that also carries requests to
+// `router`
+func add_events(router *gin.Engine, hub *event_hub) {
+	router.GET("/events/media", func(c *gin.Context) {
+		c.Writer.Header().Set("Content-Type", "text/event-stream")
+		c.Writer.Header().Set("Cache-Control", "no-cache")
+		c.Writer.Header().Set("Connection", "keep-alive")
+		c.Writer.Header().Set("Transfer-Encoding", "chunked")
+		// browsers send the header when they reconnect by themselves,
+		// the query parameter is for clients that reconnect manually
+		last_id := c.GetHeader("Last-Event-ID")
+		if last_id == "" {
+			last_id = c.Query("last_event_id")
+		}
+		ch, backlog, unsubscribe := hub.subscribe(last_id)
+		defer unsubscribe()
+		for _, ev := range backlog {
+			ev.render(c)
+		}
+		c.Writer.Flush()
+		c.Stream(func(_ io.Writer) bool {
+			select {
+			case ev, ok := <-ch:
+				if ok {
+					log.Printf("sending event to client")
+					ev.render(c)
+					log.Printf("event sent")
+				}
+				return ok
+			// don't keep listening for a client that is gone
+			case <-c.Request.Context().Done():
+				return false
+			}
+		})
+	})
+
+	router.GET("/ws", func(c *gin.Context) {
+		token := token_of(c.Request)
+		ch, backlog, unsubscribe := hub.subscribe(c.Query("last_event_id"))
+		defer unsubscribe()
+		server := websocket.Server{
+			// same policy as the REST routes, which allow any origin
+			Handshake: func(*websocket.Config, *http.Request) error { return nil },
+			Handler: func(ws *websocket.Conn) {
+				serve_ws(ws, router, token, ch, backlog)
+			},
+		}
+		server.ServeHTTP(c.Writer, c.Request)
+	})
+}
+
+// Request sent by a WebSocket client in place of an HTTP request
+type ws_request struct {
+	Id     string `json:"id"`
+	Method string `json:"method"`
+	Path   string `json:"path"`
+	// URL-encoded query string
+	Query string `json:"query"`
+}
+
+// Message sent to a WebSocket client: either the response to a request
+// or a media event
+type ws_message struct {
+	Type string `json:"type"`
+	// Request id for responses, event id for events
+	Id     string `json:"id,omitempty"`
+	Status int    `json:"status,omitempty"`
+	Body   string `json:"body,omitempty"`
+	Data   gin.H  `json:"data,omitempty"`
+}
+
+// Carry requests, their responses and media events over one WebSocket.
+// Requests are served by `handler` exactly like their HTTP counterparts,
+// authenticated with the `token` the socket was opened with. Each is
+// served on its own, so a slow request doesn't hold up the others.
+func serve_ws(ws *websocket.Conn, handler http.Handler, token string, events chan event, backlog []event) {
+	go func() {
+		for _, ev := range backlog {
+			websocket.JSON.Send(ws, ws_message{Type: "event", Id: ev.id, Data: ev.data})
+		}
+		for ev := range events {
+			if err := websocket.JSON.Send(ws, ws_message{Type: "event", Id: ev.id, Data: ev.data}); err != nil {
+				log.Printf("ws: %s", err)
+			}
+		}
+		// events stop when the socket is done or the client falls behind.
+		// It then resumes from its last event id after reconnecting.
+		ws.Close()
+	}()
+	for {
+		var req ws_request
+		if err := websocket.JSON.Receive(ws, &req); err != nil {
+			if err != io.EOF {
+				log.Printf("ws: %s", err)
+			}
+			return
+		}
+		go func() {
+			if err := websocket.JSON.Send(ws, serve_ws_request(handler, token, req)); err != nil {
+				log.Printf("ws: %s", err)
+			}
+		}()
+	}
+}
+
+// Response to `req`, served by `handler`
+func serve_ws_request(handler http.Handler, token string, req ws_request) ws_message {
+	res := ws_message{Type: "response", Id: req.Id}
+	target := req.Path
+	if req.Query != "" {
+		target += "?" + req.Query
+	}
+	r, err := http.NewRequest(req.Method, target, nil)
+	if err != nil {
+		res.Status = http.StatusBadRequest
+		res.Body = fmt.Sprintf("invalid request: %s", err)
+		return res
+	}
+	if !ws_route(r.URL.Path) {
+		res.Status = http.StatusNotFound
+		res.Body = fmt.Sprintf("%s can't be requested over a WebSocket", r.URL.Path)
+		return res
+	}
+	if token != "" {
+		r.Header.Set("Authorization", "Bearer "+token)
+	}
+	w := &ws_response_writer{header: make(http.Header)}
+	handler.ServeHTTP(w, r)
+	res.Status = w.status
+	res.Body = w.body.String()
+	return res
+}
+
+// Whether `p` is a route that may be requested over a WebSocket. The
+// others, like /ws itself, only make sense over HTTP.
+func ws_route(p string) bool {
+	if path.Clean(p) != p {
+		return false
+	}
+	return p == "/media" || strings.HasPrefix(p, "/media/") || strings.HasPrefix(p, "/hashes/")
+}
+
+// Collects the response to a request made over a WebSocket
+type ws_response_writer struct {
+	header http.Header
+	status int
+	body   bytes.Buffer
+}
+
+func (w *ws_response_writer) Header() http.Header {
+	return w.header
+}
+
+func (w *ws_response_writer) WriteHeader(status int) {
+	if w.status == 0 {
+		w.status = status
+	}
+}
+
+func (w *ws_response_writer) Write(data []byte) (int, error) {
+	w.WriteHeader(http.StatusOK)
+	return w.body.Write(data)
+}
@@ -0,0 +1,291 @@
+package main
+
+import (
+	"bufio"
+	"encoding/json"
+	"net/http"
+	"net/http/httptest"
+	"strconv"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"golang.org/x/net/websocket"
+)
+
+// The API serving `media`, with events from `hub`. Requires a login if
+// API_PASSWORD is set.
+func test_server(t *testing.T, media Collection, hub *event_hub) *httptest.Server {
+	gin.SetMode(gin.TestMode)
+	router := gin.New()
+	add_auth(router)
+	add_collection(router, media, "media", hub.broadcast, func(string, string) error { return nil })
+	add_events(router, hub)
+	server := httptest.NewServer(router)
+	t.Cleanup(server.Close)
+	return server
+}
+
+// Open a WebSocket to `server`'s /ws with query string `query`, sending
+// `cookies`
+func dial(t *testing.T, server *httptest.Server, query string, cookies ...*http.Cookie) (*websocket.Conn, error) {
+	url := "ws" + strings.TrimPrefix(server.URL, "http") + "/ws" + query
+	config, err := websocket.NewConfig(url, server.URL)
+	if err != nil {
+		return nil, err
+	}
+	for _, cookie := range cookies {
+		config.Header.Add("Cookie", cookie.String())
+	}
+	ws, err := websocket.DialConfig(config)
+	if err == nil {
+		t.Cleanup(func() { ws.Close() })
+	}
+	return ws, err
+}
+
+func must_dial(t *testing.T, server *httptest.Server, query string, cookies ...*http.Cookie) *websocket.Conn {
+	t.Helper()
+	ws, err := dial(t, server, query, cookies...)
+	if err != nil {
+		t.Fatal(err)
+	}
+	return ws
+}
+
+// Next message on `ws`
+func receive(t *testing.T, ws *websocket.Conn) ws_message {
+	t.Helper()
+	ws.SetReadDeadline(time.Now().Add(5 * time.Second))
+	var message ws_message
+	if err := websocket.JSON.Receive(ws, &message); err != nil {
+		t.Fatal(err)
+	}
+	return message
+}
+
+func send(t *testing.T, ws *websocket.Conn, req ws_request) {
+	t.Helper()
+	if err := websocket.JSON.Send(ws, req); err != nil {
+		t.Fatal(err)
+	}
+}
+
+// Send `req` and wait for its response, assuming no events arrive
+func request(t *testing.T, ws *websocket.Conn, req ws_request) ws_message {
+	t.Helper()
+	send(t, ws, req)
+	res := receive(t, ws)
+	if res.Type != "response" || res.Id != req.Id {
+		t.Fatalf("expected the response to %s, got %+v", req.Id, res)
+	}
+	return res
+}
+
+func TestWsCarriesRequestsResponsesAndEvents(t *testing.T) {
+	media := NewMemCollection()
+	id, _ := media.Create(Item{Title: "Cat", Format: "mp4"})
+	media.Create(Item{Title: "Dog", Format: "mp4"})
+	ws := must_dial(t, test_server(t, media, new_event_hub()), "")
+
+	res := request(t, ws, ws_request{Id: "1", Method: "GET", Path: "/media", Query: "limit=10&q=cat"})
+	var page struct {
+		Items []struct {
+			Id string `json:"id"`
+		} `json:"items"`
+	}
+	if err := json.Unmarshal([]byte(res.Body), &page); err != nil || res.Status != http.StatusOK {
+		t.Fatalf("unexpected response %+v", res)
+	}
+	if len(page.Items) != 1 || page.Items[0].Id != id {
+		t.Fatalf("expected only %s, got %+v", id, page.Items)
+	}
+
+	// the event and the response may arrive in either order
+	send(t, ws, ws_request{Id: "2", Method: "PATCH", Path: "/media/" + id, Query: "f=title&v=Kitten&rid=r-1"})
+	messages := map[string]ws_message{}
+	for i := 0; i < 2; i++ {
+		message := receive(t, ws)
+		messages[message.Type] = message
+	}
+	if res := messages["response"]; res.Id != "2" || res.Status != http.StatusOK {
+		t.Fatalf("unexpected response %+v", res)
+	}
+	if ev := messages["event"]; ev.Data["type"] != "updated" || ev.Data["request_id"] != "r-1" {
+		t.Fatalf("unexpected event %+v", ev)
+	}
+}
+
+func TestWsRejectsMalformedRequestsAndOtherRoutes(t *testing.T) {
+	ws := must_dial(t, test_server(t, NewMemCollection(), new_event_hub()), "")
+	for i, req := range []struct {
+		method, path string
+		status       int
+	}{
+		{"GET /", "/media", http.StatusBadRequest},
+		{"GET", "/media/%zz", http.StatusBadRequest},
+		{"GET", "/ws", http.StatusNotFound},
+		{"GET", "/events/media", http.StatusNotFound},
+		{"GET", "/media/../ws", http.StatusNotFound},
+	} {
+		id := strconv.Itoa(i)
+		res := request(t, ws, ws_request{Id: id, Method: req.method, Path: req.path})
+		if res.Status != req.status {
+			t.Errorf("%s %s: expected %d, got %+v", req.method, req.path, req.status, res)
+		}
+	}
+	// the socket is still there
+	res := request(t, ws, ws_request{Id: "last", Method: "GET", Path: "/media", Query: "limit=1"})
+	if res.Status != http.StatusOK {
+		t.Fatalf("unexpected response %+v", res)
+	}
+}
+
+// Takes until `release` is closed to look up item "slow"
+type slow_collection struct {
+	*MemCollection
+	release chan struct{}
+}
+
+func (coll slow_collection) Get(id string) (Item, bool, error) {
+	if id == "slow" {
+		<-coll.release
+	}
+	return coll.MemCollection.Get(id)
+}
+
+func TestWsServesRequestsConcurrently(t *testing.T) {
+	media := slow_collection{NewMemCollection(), make(chan struct{})}
+	ws := must_dial(t, test_server(t, media, new_event_hub()), "")
+	defer close(media.release)
+
+	send(t, ws, ws_request{Id: "slow", Method: "GET", Path: "/media/slow"})
+	res := request(t, ws, ws_request{Id: "fast", Method: "GET", Path: "/media", Query: "limit=1"})
+	if res.Status != http.StatusOK {
+		t.Fatalf("unexpected response %+v", res)
+	}
+}
+
+func TestWsReplaysMissedEvents(t *testing.T) {
+	hub := new_event_hub()
+	server := test_server(t, NewMemCollection(), hub)
+	hub.broadcast(gin.H{"type": "removed", "id": "1"})
+	hub.broadcast(gin.H{"type": "removed", "id": "2"})
+	first := hub.history[0].id
+
+	ws := must_dial(t, server, "?last_event_id="+first)
+	if ev := receive(t, ws); ev.Type != "event" || ev.Id != hub.history[1].id || ev.Data["id"] != "2" {
+		t.Fatalf("expected the second event, got %+v", ev)
+	}
+	hub.broadcast(gin.H{"type": "removed", "id": "3"})
+	if ev := receive(t, ws); ev.Data["id"] != "3" {
+		t.Fatalf("expected the third event, got %+v", ev)
+	}
+
+	forgotten := must_dial(t, server, "?last_event_id=before-a-restart")
+	if ev := receive(t, forgotten); ev.Data["type"] != "resync" {
+		t.Fatalf("expected a resync, got %+v", ev)
+	}
+}
+
+func TestWsRequiresAToken(t *testing.T) {
+	require_password(t, "secret")
+	server := test_server(t, NewMemCollection(), new_event_hub())
+
+	if _, err := dial(t, server, ""); err == nil {
+		t.Fatal("opened a socket without a token")
+	}
+	res := login(t, server, "secret")
+	var cookie *http.Cookie
+	for _, c := range res.Cookies() {
+		if c.Name == TOKEN_COOKIE {
+			cookie = c
+		}
+	}
+	if res.StatusCode != http.StatusOK || cookie == nil || !cookie.HttpOnly {
+		t.Fatalf("expected an HttpOnly cookie with the token, got %d %+v", res.StatusCode, res.Cookies())
+	}
+	// tokens in the URL end up in logs and aren't taken
+	if _, err := dial(t, server, "?access_token="+cookie.Value); err == nil {
+		t.Fatal("opened a socket with a token in the URL")
+	}
+
+	ws := must_dial(t, server, "", cookie)
+	// requests over the socket are made with its token
+	if res := request(t, ws, ws_request{Id: "1", Method: "GET", Path: "/media", Query: "limit=1"}); res.Status != http.StatusOK {
+		t.Fatalf("unexpected response %+v", res)
+	}
+}
+
+// Next event on an event stream, as its id and data
+func next_event(t *testing.T, stream *bufio.Reader) (string, gin.H) {
+	t.Helper()
+	var id string
+	var data gin.H
+	for {
+		line, err := stream.ReadString('\n')
+		if err != nil {
+			t.Fatal(err)
+		}
+		line = strings.TrimSuffix(line, "\n")
+		switch {
+		case line == "" && data != nil:
+			return id, data
+		case strings.HasPrefix(line, "id:"):
+			id = strings.TrimPrefix(line, "id:")
+		case strings.HasPrefix(line, "data:"):
+			if err := json.Unmarshal([]byte(strings.TrimPrefix(line, "data:")), &data); err != nil {
+				t.Fatal(err)
+			}
+		}
+	}
+}
+
+func TestEventStreamResumesFromTheLastEventId(t *testing.T) {
+	hub := new_event_hub()
+	server := test_server(t, NewMemCollection(), hub)
+	hub.broadcast(gin.H{"type": "removed", "id": "1"})
+	hub.broadcast(gin.H{"type": "removed", "id": "2"})
+
+	req, _ := http.NewRequest("GET", server.URL+"/events/media", nil)
+	req.Header.Set("Last-Event-ID", hub.history[0].id)
+	client := http.Client{Timeout: 5 * time.Second}
+	res, err := client.Do(req)
+	if err != nil {
+		t.Fatal(err)
+	}
+	t.Cleanup(func() { res.Body.Close() })
+	stream := bufio.NewReader(res.Body)
+
+	if id, data := next_event(t, stream); id != hub.history[1].id || data["id"] != "2" {
+		t.Fatalf("expected the second event, got %s %v", id, data)
+	}
+	hub.broadcast(gin.H{"type": "removed", "id": "3"})
+	if _, data := next_event(t, stream); data["id"] != "3" {
+		t.Fatalf("expected the third event, got %v", data)
+	}
+}
+
+func TestSlowClientsAreDisconnected(t *testing.T) {
+	hub := new_event_hub()
+	ch, _, unsubscribe := hub.subscribe("")
+	for i := 0; i <= CLIENT_BUFFER; i++ {
+		hub.broadcast(gin.H{"type": "removed", "id": strconv.Itoa(i)})
+	}
+	received := []event{}
+	for ev := range ch {
+		received = append(received, ev)
+	}
+	if len(received) != CLIENT_BUFFER {
+		t.Fatalf("expected %d events before the channel closed, got %d", CLIENT_BUFFER, len(received))
+	}
+	// already closed, which must not panic
+	unsubscribe()
+
+	_, missed, unsubscribe := hub.subscribe(received[len(received)-1].id)
+	defer unsubscribe()
+	if len(missed) != 1 || missed[0].data["id"] != strconv.Itoa(CLIENT_BUFFER) {
+		t.Fatalf("expected to catch up on the last event, got %+v", missed)
+	}
+}
@@ -0,0 +1,87 @@
+package main
+
+import (
+	"crypto/sha256"
+	"encoding/hex"
+	"io"
+	"log"
+	"os"
+	"sync"
+)
+
+// SHA-256 content hashes of indexed files, so clients can skip uploading
+// files the library already has
+type hash_index struct {
+	mu sync.Mutex
+	// ids of the items with the same content, by hash
+	ids map[string]map[string]struct{}
+	// hash by item id
+	hashes map[string]string
+}
+
+func new_hash_index() *hash_index {
+	return &hash_index{ids: make(map[string]map[string]struct{}), hashes: make(map[string]string)}
+}
+
+// Hash the file at `p` and remember it as item `id`. Reads the whole
+// file, so call it off the request path. Directories are skipped.
+func (h *hash_index) add(id string, p string) {
+	if stat, err := os.Stat(p); err != nil || stat.IsDir() {
+		return
+	}
+	sum, err := sha256_file(p)
+	if err != nil {
+		log.Printf("hash %s: %s", p, err)
+		return
+	}
+	h.mu.Lock()
+	defer h.mu.Unlock()
+	h.forget(id)
+	if h.ids[sum] == nil {
+		h.ids[sum] = make(map[string]struct{})
+	}
+	h.ids[sum][id] = struct{}{}
+	h.hashes[id] = sum
+}
+
+func (h *hash_index) remove(id string) {
+	h.mu.Lock()
+	defer h.mu.Unlock()
+	h.forget(id)
+}
+
+// Must be called with `mu` held
+func (h *hash_index) forget(id string) {
+	if sum, ok := h.hashes[id]; ok {
+		delete(h.ids[sum], id)
+		if len(h.ids[sum]) == 0 {
+			delete(h.ids, sum)
+		}
+		delete(h.hashes, id)
+	}
+}
+
+// Ids of the items whose content hashes to `sum`. Items removed while
+// they were being hashed may linger here.
+func (h *hash_index) lookup(sum string) []string {
+	h.mu.Lock()
+	defer h.mu.Unlock()
+	ids := make([]string, 0, len(h.ids[sum]))
+	for id := range h.ids[sum] {
+		ids = append(ids, id)
+	}
+	return ids
+}
+
+func sha256_file(p string) (string, error) {
+	f, err := os.Open(p)
+	if err != nil {
+		return "", err
+	}
+	defer f.Close()
+	hash := sha256.New()
+	if _, err := io.Copy(hash, f); err != nil {
+		return "", err
+	}
+	return hex.EncodeToString(hash.Sum(nil)), nil
+}
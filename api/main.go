@@ -4,14 +4,16 @@ import (
 	"bytes"
 	"encoding/json"
 	"fmt"
-	"io"
 	"log"
 	"net/http"
 	"os"
 	"os/exec"
 	"path"
+	"sort"
+	"strconv"
 	"strings"
 	"sync"
+	"time"
 
 	"github.com/fsnotify/fsnotify"
 	"github.com/gin-gonic/gin"
@@ -21,16 +23,81 @@ var MEDIA_SERVER_URL = os.Getenv("MEDIA_SERVER_URL")
 
 type Collection interface {
 	List() (map[string]Item, error)
-	Create(string, string, string) (string, error)
+	Create(Item) (string, error)
 	Get(id string) (Item, bool, error)
 	Update(id string, field string, value string) (bool, error)
+	SetTrashed(id string, trashed bool) (bool, error)
 	Drop(id string) error
 }
 
 type Item struct {
-	Url    string
-	Title  string
-	Format string
+	Url        string
+	Title      string
+	Format     string
+	Collection string
+	Tags       []string
+	Info       Info
+	Trashed    bool
+	// Location on disk, if the item was discovered by indexing
+	Path string `json:"-"`
+}
+
+// Technical metadata mined with `ffprobe`. Zero values mean unknown.
+type Info struct {
+	Duration   float64
+	Width      int
+	Height     int
+	VideoCodec string
+	AudioCodec string
+	Bitrate    int64
+	Size       int64
+	Added      time.Time
+}
+
+// Ids of indexed items by their path on disk, shared by the watcher and
+// the routes that delete files
+type path_index struct {
+	mu  sync.Mutex
+	ids map[string]string
+}
+
+func new_path_index() *path_index {
+	return &path_index{ids: make(map[string]string)}
+}
+
+func (paths *path_index) add(p string, id string) {
+	paths.mu.Lock()
+	defer paths.mu.Unlock()
+	paths.ids[p] = id
+}
+
+func (paths *path_index) has(p string) bool {
+	paths.mu.Lock()
+	defer paths.mu.Unlock()
+	_, ok := paths.ids[p]
+	return ok
+}
+
+// Forget `p`, returning the id it had
+func (paths *path_index) take(p string) (string, bool) {
+	paths.mu.Lock()
+	defer paths.mu.Unlock()
+	id, ok := paths.ids[p]
+	delete(paths.ids, p)
+	return id, ok
+}
+
+// Delete the file at `p` and forget it in one go, so that the watcher
+// doesn't report the removal a second time. A file that is already gone
+// counts as deleted.
+func (paths *path_index) remove_file(p string) error {
+	paths.mu.Lock()
+	defer paths.mu.Unlock()
+	if err := os.Remove(p); err != nil && !os.IsNotExist(err) {
+		return err
+	}
+	delete(paths.ids, p)
+	return nil
 }
 
 func main() {
@@ -39,107 +106,226 @@ func main() {
 		log.Fatalf("please set env var MEDIA_SERVER_URL")
 	}
 
-	path_to_id := make(map[string]string)
+	paths := new_path_index()
 	media := NewMemCollection()
+	hashes := new_hash_index()
+	// Directories aren't media. They only show up as the folders of the
+	// files in them.
 	index := func(p string) gin.H {
+		if is_hidden(p) || paths.has(p) {
+			return nil
+		}
+		stat, err := os.Stat(p)
+		if err != nil || stat.IsDir() {
+			return nil
+		}
 		title, format := extract_title_format(p)
-		if format == "unknown" {
-			title, format = ffprobe(p)
+		probe_title, probe_format, info, err := ffprobe(p)
+		if err != nil {
+			log.Printf("ffprobe %s: %s", p, err)
+		} else if format == "unknown" {
+			title, format = probe_title, probe_format
+		}
+		meta, err := read_sidecar(p)
+		if err != nil {
+			log.Printf("sidecar of %s: %s", p, err)
+		}
+		if meta.Title != "" {
+			title = meta.Title
+		}
+		if info.Size == 0 {
+			info.Size = stat.Size()
 		}
+		info.Added = stat.ModTime()
 		url := fmt.Sprintf("%s%s", MEDIA_SERVER_URL, p[len("/data"):])
-		id, err := media.Create(url, title, format)
+		item := Item{
+			Url:     url,
+			Title:   title,
+			Format:  format,
+			Tags:    meta.tags(),
+			Info:    info,
+			Trashed: meta.Trashed,
+			Path:    p,
+		}
+		id, err := media.Create(item)
 		if err != nil {
 			log.Print(err)
 			return nil
 		}
-		path_to_id[p] = id
-		return gin.H{
-			"id":     id,
-			"title":  title,
-			"format": format,
-			"url":    url,
-		}
-
+		paths.add(p, id)
+		return item_json(id, item)
 	}
 	walk("/data", index)
+	// hashing reads every file, so let the API start serving meanwhile
+	indexed := make(map[string]string, len(paths.ids))
+	for p, id := range paths.ids {
+		indexed[p] = id
+	}
+	go func() {
+		for p, id := range indexed {
+			hashes.add(id, p)
+		}
+	}()
 
-	clients := make(map[chan gin.H]struct{})
-	mu := sync.Mutex{}
+	hub := new_event_hub()
+	created := func(p string) gin.H {
+		item := index(p)
+		if item != nil {
+			go hashes.add(item["id"].(string), p)
+			hub.broadcast(gin.H{"type": "created", "item": item})
+		}
+		return item
+	}
 	watch("/data", func(ev fsnotify.Event) {
 		switch ev.Op {
 		case fsnotify.Create:
 			log.Printf("create %s", ev.Name)
-			item := index(ev.Name)
-			mu.Lock()
-			log.Printf("clients: %d", len(clients))
-			for client := range clients {
-				client <- item
+			created(ev.Name)
+			// files may have been moved in before the directory was watched
+			if stat, err := os.Stat(ev.Name); err == nil && stat.IsDir() {
+				walk(ev.Name, created)
 			}
-			mu.Unlock()
 		case fsnotify.Rename, fsnotify.Remove:
 			log.Printf("remove %s", ev.Name)
-			id := path_to_id[ev.Name]
+			id, ok := paths.take(ev.Name)
+			if !ok {
+				return
+			}
 			if err := media.Drop(id); err != nil {
 				log.Print(err)
 			}
+			hashes.remove(id)
+			hub.broadcast(gin.H{"type": "removed", "id": id})
 		}
 	})
 
 	router := gin.Default()
-	add_collection(router, media, "media")
-
-	router.GET("/events/media", func(c *gin.Context) {
-		c.Writer.Header().Set("Content-Type", "text/event-stream")
-		c.Writer.Header().Set("Cache-Control", "no-cache")
-		c.Writer.Header().Set("Connection", "keep-alive")
-		c.Writer.Header().Set("Transfer-Encoding", "chunked")
-		ch := make(chan gin.H)
-		log.Printf("new client")
-		mu.Lock()
-		clients[ch] = struct{}{}
-		mu.Unlock()
-		defer func() {
-			log.Printf("closing client channel")
-			mu.Lock()
-			close(ch)
-			delete(clients, ch)
-			mu.Unlock()
-		}()
-		c.Stream(func(_ io.Writer) bool {
-			if event, ok := <-ch; ok {
-				log.Printf("sending event to client")
-				c.SSEvent("message", event)
-				log.Printf("event sent")
-				return true
+	add_auth(router)
+	// files deleted through the API are gone before the watcher hears of it
+	remove_file := func(id string, p string) error {
+		if err := paths.remove_file(p); err != nil {
+			return err
+		}
+		hashes.remove(id)
+		return nil
+	}
+	add_collection(router, media, "media", hub.broadcast, remove_file)
+
+	// The item whose content has SHA-256 hash `sha256` (lowercase hex),
+	// so clients can skip uploading files the library already has
+	router.GET("/hashes/:sha256", func(c *gin.Context) {
+		for _, id := range hashes.lookup(strings.ToLower(c.Param("sha256"))) {
+			if item, ok, err := media.Get(id); ok {
+				c.JSON(http.StatusOK, item_json(id, item))
+				return
+			} else if err != nil {
+				c.Error(err)
+				c.Status(http.StatusInternalServerError)
+				return
 			}
-			return false
-		})
+		}
+		c.Status(http.StatusNotFound)
 	})
 
+	add_events(router, hub)
+
 	log.Fatal(router.Run())
 }
 
-func add_collection(router *gin.Engine, coll Collection, name string) {
+// Register REST routes for `coll` under `name`. Changes made through
+// these routes are announced with `broadcast`. Files of items deleted
+// for good are removed with `remove_file`.
+func add_collection(router *gin.Engine, coll Collection, name string, broadcast func(gin.H), remove_file func(id string, p string) error) {
+	// announce the current state of item `id`, crediting the change to
+	// the client request `request_id` if there is one
+	updated := func(id string, request_id string) {
+		if item, ok, err := coll.Get(id); ok {
+			event := gin.H{"type": "updated", "item": item_json(id, item)}
+			if request_id != "" {
+				event["request_id"] = request_id
+			}
+			broadcast(event)
+		} else if err != nil {
+			log.Print(err)
+		}
+	}
+
 	group := router.Group(name)
 
+	// Without `limit` the whole collection is returned as an array. With
+	// it, items are filtered by `q`, `kind`, `trashed` and `id` and
+	// returned a page at a time, ordered by title and id. `cursor` is
+	// taken from the `next` field of the previous page.
 	group.GET("", func(c *gin.Context) {
-		if items, err := coll.List(); err == nil {
-			res := make([]map[string]string, len(items))
+		items, err := coll.List()
+		if err != nil {
+			c.Error(err)
+			c.Status(http.StatusInternalServerError)
+			return
+		}
+		if c.Query("limit") == "" {
+			res := make([]gin.H, len(items))
 			i := 0
 			for id, item := range items {
-				res[i] = map[string]string{
-					"id":     id,
-					"url":    item.Url,
-					"title":  item.Title,
-					"format": item.Format,
-				}
+				res[i] = item_json(id, item)
 				i++
 			}
 			c.JSON(http.StatusOK, res)
-		} else {
-			c.Error(err)
-			c.Status(http.StatusInternalServerError)
+			return
+		}
+		limit, err := strconv.Atoi(c.Query("limit"))
+		if err != nil || limit <= 0 {
+			c.String(http.StatusBadRequest, "invalid limit: %s", c.Query("limit"))
+			return
+		}
+		var after *page_cursor
+		if cursor := c.Query("cursor"); cursor != "" {
+			after = &page_cursor{}
+			if err := json.Unmarshal([]byte(cursor), after); err != nil {
+				c.String(http.StatusBadRequest, "invalid cursor: %s", cursor)
+				return
+			}
+		}
+		q := strings.ToLower(c.Query("q"))
+		ids := make([]string, 0, len(items))
+		for id, item := range items {
+			if want := c.Query("id"); want != "" && id != want {
+				continue
+			}
+			if q != "" && !strings.Contains(strings.ToLower(item.Title), q) {
+				continue
+			}
+			if kind := c.Query("kind"); kind != "" && kind_of(item.Format) != kind {
+				continue
+			}
+			if trashed := c.Query("trashed"); trashed != "" && strconv.FormatBool(item.Trashed) != trashed {
+				continue
+			}
+			ids = append(ids, id)
+		}
+		sort.Slice(ids, func(i, j int) bool {
+			a, b := items[ids[i]], items[ids[j]]
+			if a.Title == b.Title {
+				return ids[i] < ids[j]
+			}
+			return a.Title < b.Title
+		})
+		start := sort.Search(len(ids), func(i int) bool {
+			return after == nil || after.before(items[ids[i]].Title, ids[i])
+		})
+		end := min(start+limit, len(ids))
+		page := gin.H{}
+		res := make([]gin.H, 0, end-start)
+		for _, id := range ids[start:end] {
+			res = append(res, item_json(id, items[id]))
 		}
+		page["items"] = res
+		if end < len(ids) {
+			last := ids[end-1]
+			next, _ := json.Marshal(page_cursor{Title: items[last].Title, Id: last})
+			page["next"] = string(next)
+		}
+		c.JSON(http.StatusOK, page)
 	})
 
 	group.POST("", func(c *gin.Context) {
@@ -147,7 +333,7 @@ func add_collection(router *gin.Engine, coll Collection, name string) {
 		if err := c.BindJSON(&item); err != nil {
 			c.String(http.StatusBadRequest, "invalid JSON: %s", err)
 		} else {
-			if id, err := coll.Create(item.Url, item.Title, item.Format); err == nil {
+			if id, err := coll.Create(item); err == nil {
 				c.String(http.StatusAccepted, "%s", id)
 			} else {
 				c.Error(err)
@@ -168,11 +354,20 @@ func add_collection(router *gin.Engine, coll Collection, name string) {
 		}
 	})
 
+	// Set field `f` to `v`. The change is announced with the client's
+	// request id `rid` so it can match the event to its edit.
 	group.PATCH("/:id", func(c *gin.Context) {
 		id := c.Param("id")
 		field := c.Query("f")
 		value := c.Query("v")
+		switch field {
+		case "title", "format", "collection":
+		default:
+			c.String(http.StatusBadRequest, "unknown field: %s", field)
+			return
+		}
 		if ok, err := coll.Update(id, field, value); ok {
+			updated(id, c.Query("rid"))
 			c.Status(http.StatusOK)
 		} else if err == nil {
 			c.Status(http.StatusNotFound)
@@ -181,6 +376,161 @@ func add_collection(router *gin.Engine, coll Collection, name string) {
 			c.Status(http.StatusInternalServerError)
 		}
 	})
+
+	// Move an item to the trash, or remove it for good with `?permanent=true`
+	group.DELETE("/:id", func(c *gin.Context) {
+		id := c.Param("id")
+		if c.Query("permanent") != "true" {
+			if set_trashed(c, coll, id, true) {
+				updated(id, "")
+			}
+			return
+		}
+		item, ok, err := coll.Get(id)
+		if err != nil {
+			c.Error(err)
+			c.Status(http.StatusInternalServerError)
+			return
+		} else if !ok {
+			c.Status(http.StatusNotFound)
+			return
+		} else if !item.Trashed {
+			c.String(http.StatusConflict, "only items in the trash can be deleted")
+			return
+		}
+		if item.Path != "" {
+			if stat, err := os.Stat(item.Path); err == nil && stat.IsDir() {
+				c.String(http.StatusConflict, "folders can't be deleted")
+				return
+			}
+			if err := remove_file(id, item.Path); err != nil {
+				c.Error(err)
+				c.Status(http.StatusInternalServerError)
+				return
+			}
+			if err := os.Remove(sidecar_path(item.Path)); err != nil && !os.IsNotExist(err) {
+				log.Print(err)
+			}
+		}
+		if err := coll.Drop(id); err != nil {
+			c.Error(err)
+			c.Status(http.StatusInternalServerError)
+			return
+		}
+		broadcast(gin.H{"type": "removed", "id": id})
+		c.Status(http.StatusOK)
+	})
+
+	group.POST("/:id/restore", func(c *gin.Context) {
+		id := c.Param("id")
+		if set_trashed(c, coll, id, false) {
+			updated(id, "")
+		}
+	})
+}
+
+// Where a page of items ends: the title and id of its last item. Pages
+// pick up after it, so that items added or removed in the meantime don't
+// shift the pages that follow.
+type page_cursor struct {
+	Title string `json:"title"`
+	Id    string `json:"id"`
+}
+
+// Whether the item titled `title` with id `id` comes after the cursor
+func (cursor page_cursor) before(title string, id string) bool {
+	if cursor.Title == title {
+		return cursor.Id < id
+	}
+	return cursor.Title < title
+}
+
+// Respond to a trash or restore request. Returns whether the item changed.
+// Items on disk keep the flag in their sidecar so it survives restarts.
+func set_trashed(c *gin.Context, coll Collection, id string, trashed bool) bool {
+	item, ok, err := coll.Get(id)
+	if err != nil {
+		c.Error(err)
+		c.Status(http.StatusInternalServerError)
+		return false
+	} else if !ok {
+		c.Status(http.StatusNotFound)
+		return false
+	}
+	if item.Path != "" {
+		if err := mark_trashed(item.Path, trashed); err != nil {
+			c.Error(err)
+			c.Status(http.StatusInternalServerError)
+			return false
+		}
+	}
+	if ok, err := coll.SetTrashed(id, trashed); ok {
+		c.Status(http.StatusOK)
+		return true
+	} else if err == nil {
+		c.Status(http.StatusNotFound)
+	} else {
+		c.Error(err)
+		c.Status(http.StatusInternalServerError)
+	}
+	return false
+}
+
+// Kind of media for a file extension. Keep in sync with `MediaKind` in the UI.
+func kind_of(format string) string {
+	switch strings.ToLower(format) {
+	case "mkv", "mp4", "ogg", "webm", "mov", "m4v":
+		return "video"
+	case "jpeg", "jpg", "png", "webp", "gif", "avif":
+		return "image"
+	case "mp3", "flac", "opus", "m4a", "wav":
+		return "audio"
+	default:
+		return "unknown"
+	}
+}
+
+// JSON representation of an item. Unknown technical metadata is omitted.
+func item_json(id string, item Item) gin.H {
+	res := gin.H{
+		"id":     id,
+		"url":    item.Url,
+		"title":  item.Title,
+		"format": item.Format,
+	}
+	if item.Collection != "" {
+		res["collection"] = item.Collection
+	}
+	if len(item.Tags) > 0 {
+		res["tags"] = item.Tags
+	}
+	if item.Trashed {
+		res["trashed"] = true
+	}
+	info := item.Info
+	if info.Duration > 0 {
+		res["duration"] = info.Duration
+	}
+	if info.Width > 0 && info.Height > 0 {
+		res["width"] = info.Width
+		res["height"] = info.Height
+	}
+	if info.VideoCodec != "" {
+		res["video_codec"] = info.VideoCodec
+	}
+	if info.AudioCodec != "" {
+		res["audio_codec"] = info.AudioCodec
+	}
+	if info.Bitrate > 0 {
+		res["bitrate"] = info.Bitrate
+	}
+	if info.Size > 0 {
+		res["size"] = info.Size
+	}
+	if !info.Added.IsZero() {
+		res["added"] = info.Added.UTC().Format(time.RFC3339)
+	}
+	return res
 }
 
 func walk(dir string, f func(string) gin.H) {
@@ -198,19 +548,46 @@ func walk(dir string, f func(string) gin.H) {
 	}
 }
 
+// Call `f` with changes to `dir` and its subdirectories, including ones
+// created later
 func watch(dir string, f func(fsnotify.Event)) {
 	watcher, err := fsnotify.NewWatcher()
 	if err != nil {
 		log.Fatal(err)
 	}
-	watcher.Add(dir)
+	watch_tree(watcher, dir)
 	go func() {
 		for {
-			f(<-watcher.Events)
+			ev := <-watcher.Events
+			if ev.Op == fsnotify.Create {
+				if stat, err := os.Stat(ev.Name); err == nil && stat.IsDir() {
+					watch_tree(watcher, ev.Name)
+				}
+			}
+			f(ev)
 		}
 	}()
 }
 
+func watch_tree(watcher *fsnotify.Watcher, dir string) {
+	if err := watcher.Add(dir); err != nil {
+		log.Print(err)
+		return
+	}
+	if entries, err := os.ReadDir(dir); err == nil {
+		for _, entry := range entries {
+			if entry.IsDir() {
+				watch_tree(watcher, path.Join(dir, entry.Name()))
+			}
+		}
+	}
+}
+
+// Hidden files are sidecars and uploads that are still being moved in
+func is_hidden(p string) bool {
+	return strings.HasPrefix(path.Base(p), ".")
+}
+
 func extract_title_format(p string) (string, string) {
 	base := path.Base(p)
 	ext := path.Ext(p)
@@ -222,29 +599,57 @@ func extract_title_format(p string) (string, string) {
 	}
 }
 
-func ffprobe(p string) (string, string) {
+func ffprobe(p string) (string, string, Info, error) {
+	var info Info
 	cmd := exec.Command(
 		"ffprobe",
 		"-v", "quiet",
 		"-print_format", "json",
 		"-show_format",
+		"-show_streams",
 		p,
 	)
 	cmd.Stderr = log.Writer()
 	buf := new(bytes.Buffer)
 	cmd.Stdout = buf
 	if err := cmd.Run(); err != nil {
-		log.Fatal(err)
+		return "", "", info, err
 	}
 	var v struct {
 		Format struct {
 			FileName   string `json:"filename"`
 			FormatName string `json:"format_name"`
+			Duration   string `json:"duration"`
+			BitRate    string `json:"bit_rate"`
+			Size       string `json:"size"`
 			Tags       map[string]string
 		}
+		Streams []struct {
+			CodecType string `json:"codec_type"`
+			CodecName string `json:"codec_name"`
+			Width     int    `json:"width"`
+			Height    int    `json:"height"`
+		}
 	}
 	if err := json.Unmarshal(buf.Bytes(), &v); err != nil {
-		log.Fatal(err)
+		return "", "", info, err
+	}
+	info.Duration, _ = strconv.ParseFloat(v.Format.Duration, 64)
+	info.Bitrate, _ = strconv.ParseInt(v.Format.BitRate, 10, 64)
+	info.Size, _ = strconv.ParseInt(v.Format.Size, 10, 64)
+	for _, stream := range v.Streams {
+		switch stream.CodecType {
+		case "video":
+			if info.VideoCodec == "" {
+				info.VideoCodec = stream.CodecName
+				info.Width = stream.Width
+				info.Height = stream.Height
+			}
+		case "audio":
+			if info.AudioCodec == "" {
+				info.AudioCodec = stream.CodecName
+			}
+		}
 	}
 	var title string
 	title, ok := v.Format.Tags["title"]
@@ -252,9 +657,9 @@ func ffprobe(p string) (string, string) {
 		title = path.Base(v.Format.FileName)
 	}
 	if strings.HasPrefix(v.Format.FormatName, "matroska") {
-		return title, "mkv"
+		return title, "mkv", info, nil
 	} else if strings.HasPrefix(v.Format.FormatName, "mov,mp4") {
-		return title, "mp4"
+		return title, "mp4", info, nil
 	}
-	return title, "unknown"
+	return title, "unknown", info, nil
 }
@@ -16,14 +16,14 @@ func NewMemCollection() *MemCollection {
 	return &MemCollection{items: make(map[string]Item)}
 }
 
-func (coll *MemCollection) Create(url string, title string, format string) (string, error) {
+func (coll *MemCollection) Create(item Item) (string, error) {
 	coll.mu.Lock()
 	defer coll.mu.Unlock()
 	if id, err := uuid.NewRandom(); err != nil {
 		return "", err
 	} else {
 		id := id.String()
-		coll.items[id] = Item{Url: url, Title: title, Format: format}
+		coll.items[id] = item
 		return id, nil
 	}
 }
@@ -50,6 +50,8 @@ func (coll *MemCollection) Update(id string, field string, value string) (bool,
 			item.Title = value
 		case "format":
 			item.Format = value
+		case "collection":
+			item.Collection = value
 		}
 		coll.items[id] = item
 		return true, nil
@@ -58,6 +60,18 @@ func (coll *MemCollection) Update(id string, field string, value string) (bool,
 	}
 }
 
+func (coll *MemCollection) SetTrashed(id string, trashed bool) (bool, error) {
+	coll.mu.Lock()
+	defer coll.mu.Unlock()
+	if item, ok := coll.items[id]; ok {
+		item.Trashed = trashed
+		coll.items[id] = item
+		return true, nil
+	} else {
+		return false, nil
+	}
+}
+
 func (coll *MemCollection) Drop(id string) error {
 	coll.mu.Lock()
 	defer coll.mu.Unlock()
@@ -0,0 +1,66 @@
+package main
+
+import (
+	"encoding/json"
+	"os"
+	"path"
+	"strings"
+)
+
+// Details given to an upload, which the tusd `post-finish` hook writes
+// next to the file as `.<name>.json` before moving the file in. The API
+// adds to it what has to survive a restart.
+type sidecar struct {
+	Title string `json:"title"`
+	// Comma-separated
+	Tags    string `json:"tags"`
+	Trashed bool   `json:"trashed,omitempty"`
+}
+
+func sidecar_path(p string) string {
+	return path.Join(path.Dir(p), "."+path.Base(p)+".json")
+}
+
+// Sidecar of the file at `p`. Files without one get the zero value.
+func read_sidecar(p string) (sidecar, error) {
+	var meta sidecar
+	data, err := os.ReadFile(sidecar_path(p))
+	if os.IsNotExist(err) {
+		return meta, nil
+	} else if err != nil {
+		return meta, err
+	}
+	err = json.Unmarshal(data, &meta)
+	return meta, err
+}
+
+func (meta sidecar) tags() []string {
+	var tags []string
+	for _, tag := range strings.Split(meta.Tags, ",") {
+		if tag = strings.TrimSpace(tag); tag != "" {
+			tags = append(tags, tag)
+		}
+	}
+	return tags
+}
+
+func write_sidecar(p string, meta sidecar) error {
+	data, err := json.Marshal(meta)
+	if err != nil {
+		return err
+	}
+	return os.WriteFile(sidecar_path(p), data, 0644)
+}
+
+// Remember in the sidecar of the file at `p` whether it is in the trash
+func mark_trashed(p string, trashed bool) error {
+	meta, err := read_sidecar(p)
+	if err != nil {
+		return err
+	}
+	if meta.Trashed == trashed {
+		return nil
+	}
+	meta.Trashed = trashed
+	return write_sidecar(p, meta)
+}
@@ -0,0 +1,55 @@
+//! Logging in to remotes that require it.
+//!
+//! Tokens are stored with the remote they belong to. When a server
+//! rejects one, the remote is flagged here and the UI sends the user to
+//! the login page, coming back to the same route afterwards.
+
+use leptos::*;
+
+use crate::path;
+
+/// Remote that has to be logged in to before it can be browsed
+#[derive(Clone, Copy)]
+pub struct Auth {
+    required: RwSignal<Option<String>>,
+}
+
+impl Auth {
+    pub fn new() -> Self {
+        Self {
+            required: create_rw_signal(None),
+        }
+    }
+
+    pub fn required(&self) -> Option<String> {
+        self.required.get()
+    }
+
+    /// The server of `remote` rejected its token, or it has none
+    pub fn require(&self, remote: String) {
+        self.required.set(Some(remote))
+    }
+
+    /// Logged in to `remote`
+    pub fn done(&self, remote: &str) {
+        if self.required.get_untracked().as_deref() == Some(remote) {
+            self.required.set(None)
+        }
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Login route for `remote` that returns to `next` afterwards
+pub fn login_path(remote: &str, next: &str) -> String {
+    format!(
+        "{}?remote={}&next={}",
+        path("login"),
+        js_sys::encode_uri_component(remote),
+        js_sys::encode_uri_component(next)
+    )
+}
@@ -1,9 +1,374 @@
-#[cfg(not(feature = "demo"))]
-mod http;
-#[cfg(not(feature = "demo"))]
-pub use http::*;
+use std::{fmt, rc::Rc, time::Duration};
 
-#[cfg(feature = "demo")]
+use futures::future::LocalBoxFuture;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ApiConfig,
+    data::{MediaEvent, MediaField, MediaItem, MediaPage, MediaQuery},
+    remotes::{Remote, Source},
+};
+
+mod api;
+mod hash;
+mod merged;
 mod mock;
-#[cfg(feature = "demo")]
-pub use mock::*;
+#[cfg(test)]
+pub(crate) mod stand_in;
+mod transport;
+mod tus;
+
+pub use api::{login, ApiClient};
+pub use hash::sha256;
+pub use merged::MergedClient;
+pub use mock::MockClient;
+pub use transport::{RequestPolicy, Retry, TransportKind};
+
+/// Reasons a request to the backend can fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientError {
+    /// The request never got a response
+    Network(String),
+    /// The server responded with a non-success status code and
+    /// (possibly empty) reason
+    Status(u16, String),
+    /// No response within the time allowed
+    Timeout(Duration),
+    /// The response body couldn't be decoded
+    Decode(String),
+    /// A resumable upload failed
+    Tus(String),
+    /// The request was rejected before it was sent
+    Invalid(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "network error: {}", e),
+            Self::Status(code, reason) if reason.is_empty() => {
+                write!(f, "server responded with status {}", code)
+            }
+            Self::Status(code, reason) => write!(f, "server responded with {}: {}", code, reason),
+            Self::Timeout(t) => write!(f, "no response after {}s", t.as_secs_f64()),
+            Self::Decode(e) => write!(f, "invalid response: {}", e),
+            Self::Tus(e) => write!(f, "upload failed: {}", e),
+            Self::Invalid(e) => write!(f, "invalid request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// State of the live connection that change events arrive on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Online,
+    /// The connection dropped and is being retried
+    Reconnecting,
+    /// Retries keep failing or the browser has no network
+    Offline,
+}
+
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Online => "online",
+            Self::Reconnecting => "reconnecting",
+            Self::Offline => "offline",
+        }
+    }
+}
+
+/// Changes to the library and the state of the connection they arrive on
+#[derive(Clone, Copy)]
+pub struct MediaEvents {
+    pub events: Signal<Option<MediaEvent>>,
+    pub state: Signal<ConnectionState>,
+}
+
+/// Where an upload stands
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadState {
+    /// Waiting for other uploads to finish
+    Queued,
+    /// Hashing the file to see whether the library already has it
+    Checking,
+    /// The library already has the file, under the given title. Waits for
+    /// the user to upload it anyway or skip it.
+    Duplicate(String),
+    Uploading,
+    /// Waiting to be resumed. The chunk in flight is finished first.
+    Paused,
+    Cancelled,
+    Done,
+    Failed(ClientError),
+}
+
+/// How an uploaded file is catalogued. Empty fields are left to the
+/// server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UploadMetadata {
+    /// Title to show instead of the file name
+    pub title: String,
+    pub tags: Vec<String>,
+    /// Folder to put the file in, e.g. `holidays/2024`
+    pub folder: String,
+    /// Where the file is in the folder it was picked or dropped with, e.g.
+    /// `Album/01.jpg`. Empty for files picked on their own.
+    #[serde(default)]
+    pub relative_path: String,
+}
+
+impl UploadMetadata {
+    /// Metadata from form input: comma-separated `tags` and a `folder`
+    /// that is kept from climbing out of the library
+    pub fn new(title: &str, tags: &str, folder: &str) -> Self {
+        Self {
+            title: title.trim().to_string(),
+            tags: tags
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+            folder: folder
+                .split('/')
+                .map(str::trim)
+                .filter(|p| !p.is_empty() && *p != "." && *p != "..")
+                .collect::<Vec<_>>()
+                .join("/"),
+            relative_path: String::new(),
+        }
+    }
+
+    /// Folders the file is nested in, outermost first
+    pub fn folders(&self) -> Vec<&str> {
+        let mut parts = self.relative_path.split('/').collect::<Vec<_>>();
+        parts.pop();
+        parts
+    }
+}
+
+/// How uploads share the uplink
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UploadPolicy {
+    /// Uploads that may run at once. The rest wait their turn.
+    pub concurrency: usize,
+    /// Bytes sent per request
+    pub chunk_size: f64,
+    /// Bytes per second all uploads together may send, if limited
+    pub bandwidth: Option<f64>,
+}
+
+impl Default for UploadPolicy {
+    fn default() -> Self {
+        Self {
+            concurrency: 2,
+            chunk_size: 8_000_000.0,
+            bandwidth: None,
+        }
+    }
+}
+
+/// Shared between the UI, which steers an upload, and the client, which
+/// reports its progress
+#[derive(Debug, Clone, Copy)]
+pub struct UploadTask {
+    /// Size of the file in bytes
+    pub size: f64,
+    /// Bytes the server has received
+    pub sent: RwSignal<f64>,
+    /// Bytes per second, averaged over recent chunks
+    pub rate: RwSignal<f64>,
+    pub state: RwSignal<UploadState>,
+    /// Where the server keeps the upload once it has been created
+    pub location: RwSignal<Option<String>>,
+    /// Bytes hashed while checking for duplicates
+    pub checked: RwSignal<f64>,
+    /// SHA-256 of the file once it has been hashed
+    pub sha256: RwSignal<Option<String>>,
+    /// Chunk size and bandwidth cap, followed as the upload runs
+    pub policy: Signal<UploadPolicy>,
+}
+
+impl UploadTask {
+    pub fn new(size: f64, policy: Signal<UploadPolicy>) -> Self {
+        Self {
+            size,
+            sent: create_rw_signal(0.0),
+            rate: create_rw_signal(0.0),
+            state: create_rw_signal(UploadState::Uploading),
+            location: create_rw_signal(None),
+            checked: create_rw_signal(0.0),
+            sha256: create_rw_signal(None),
+            policy,
+        }
+    }
+
+    /// The server has `sent` bytes after `bytes` more took `secs`
+    pub fn progress(&self, sent: f64, bytes: f64, secs: f64) {
+        self.sent.set(sent);
+        if secs > 0.0 {
+            let rate = bytes / secs;
+            self.rate.update(|r| {
+                *r = if *r == 0.0 { rate } else { 0.7 * *r + 0.3 * rate }
+            })
+        }
+    }
+
+    /// Seconds until the upload is done at the current rate
+    pub fn eta(&self) -> Option<f64> {
+        let rate = self.rate.get();
+        (rate > 0.0).then(|| (self.size - self.sent.get()) / rate)
+    }
+}
+
+/// Backend for fetching and modifying the media library.
+///
+/// The active client is provided to the component tree as a
+/// `RwSignal<Client>` so that it can be swapped at runtime.
+pub trait MediaClient {
+    /// Fetch one page of items matching `query`. Servers that don't
+    /// support filtering return the whole library as a single page.
+    fn get_media(&self, query: MediaQuery) -> LocalBoxFuture<'static, Result<MediaPage>>;
+
+    /// Resolves to `Ok` once the server has accepted the update. The
+    /// server echoes `request_id` in the event announcing the change.
+    fn update_media(
+        &self,
+        id: String,
+        field: MediaField,
+        request_id: String,
+    ) -> LocalBoxFuture<'static, Result<()>>;
+
+    /// Move an item to the trash
+    fn trash_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>>;
+
+    /// Take an item back out of the trash
+    fn restore_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>>;
+
+    /// Permanently delete an item that is in the trash
+    fn delete_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>>;
+
+    /// Upload `file`, catalogued with `metadata`, reporting progress to
+    /// `task` and following its state. Resumes where a previous attempt
+    /// with the same task left off.
+    fn upload_file(
+        &self,
+        file: web_sys::File,
+        metadata: UploadMetadata,
+        task: UploadTask,
+    ) -> LocalBoxFuture<'static, Result<()>>;
+
+    /// The item whose file has SHA-256 hash `sha256`, if the library has
+    /// one
+    fn find_by_hash(&self, sha256: String) -> LocalBoxFuture<'static, Result<Option<MediaItem>>>;
+
+    /// Stream of changes to the library. Must be called within a
+    /// reactive owner, which also owns the underlying connection.
+    fn media_events(&self) -> MediaEvents;
+
+    /// Whether this client serves fake data
+    fn is_demo(&self) -> bool {
+        false
+    }
+}
+
+pub type Client = Rc<dyn MediaClient>;
+
+/// How clients report back to the UI
+#[derive(Clone, Copy)]
+pub struct Hooks {
+    /// Called with the id of remotes that reject their token
+    pub on_unauthorized: Callback<String>,
+    pub on_retry: Callback<Retry>,
+}
+
+/// Client for `source`, given the remotes it reads from. The default
+/// remote follows `config` unless it was given a URL of its own.
+pub fn client_for(source: &Source, remotes: &[Remote], config: &ApiConfig, hooks: Hooks) -> Client {
+    let remote_client = |r: &Remote| -> Client {
+        let mut client = ApiClient::new(&r.origin(config), r.token.clone(), config.transport());
+        if r.follows_settings() {
+            client = client.upload_url(config.upload_url())
+        }
+        let id = r.id.clone();
+        Rc::new(
+            client
+                .rewrite(config.media_url_rewrite.clone())
+                .policy(config.request_policy(), hooks.on_retry)
+                .on_unauthorized(Callback::new(move |()| (hooks.on_unauthorized)(id.clone()))),
+        )
+    };
+    match (source, remotes) {
+        (Source::Demo, _) => Rc::new(MockClient),
+        (Source::Remote(_), [remote]) => remote_client(remote),
+        (Source::Merged, remotes) if !remotes.is_empty() => Rc::new(MergedClient::new(
+            remotes
+                .iter()
+                .map(|r| (r.id.clone(), remote_client(r)))
+                .collect(),
+        )),
+        // the remote was forgotten
+        _ => remote_client(&Remote::default_remote()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::auth::Auth;
+
+    #[wasm_bindgen_test]
+    async fn asks_for_a_login_when_a_server_rejects_its_token() {
+        stand_in::reset();
+        stand_in::respond("GET", "/api/media", 401, "login required");
+        let auth = Auth::new();
+        let hooks = Hooks {
+            on_unauthorized: Callback::new(move |remote| auth.require(remote)),
+            on_retry: Callback::new(|_| {}),
+        };
+        let nas = Remote {
+            id: "nas".to_string(),
+            name: "NAS".to_string(),
+            base_url: stand_in::BASE.to_string(),
+            token: Some("expired".to_string()),
+        };
+        let source = Source::Remote(nas.id.clone());
+        let client = client_for(&source, &[nas], &ApiConfig::default(), hooks);
+        assert_eq!(
+            client.get_media(MediaQuery::default()).await,
+            Err(ClientError::Status(401, "login required".to_string()))
+        );
+        assert_eq!(auth.required(), Some("nas".to_string()));
+    }
+
+    #[wasm_bindgen_test]
+    fn reads_upload_details_from_form_input() {
+        let metadata = UploadMetadata::new(" Holiday ", "sea, , sun ", "");
+        assert_eq!(metadata.title, "Holiday");
+        assert_eq!(metadata.tags, vec!["sea".to_string(), "sun".to_string()]);
+        assert_eq!(metadata.folder, "");
+    }
+
+    #[wasm_bindgen_test]
+    fn keeps_upload_folders_inside_the_library() {
+        for (folder, kept) in [
+            ("trips/2024", "trips/2024"),
+            ("/trips//2024/", "trips/2024"),
+            ("../../etc", "etc"),
+            ("trips/../../2024", "trips/2024"),
+            ("./ trips /.", "trips"),
+            ("..", ""),
+        ] {
+            let metadata = UploadMetadata::new("", "", folder);
+            assert_eq!(metadata.folder, kept, "{}", folder);
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::{rc::Rc, time::Duration};
+
+use super::{
+    transport::{
+        transport_for, ApiRequest, Method, RequestPolicy, Retry, RetryingTransport, Transport,
+        TransportKind,
+    },
+    tus, ClientError, MediaClient, MediaEvents, Result, UploadMetadata, UploadTask,
+};
+use crate::{
+    config::UrlRewrite,
+    data::{MediaEvent, MediaField, MediaItem, MediaPage, MediaQuery},
+    log,
+};
+use futures::{future::LocalBoxFuture, FutureExt};
+use leptos::*;
+
+/// Client for the Media Manager API
+pub struct ApiClient {
+    /// tus endpoint
+    upload_url: String,
+    token: Option<String>,
+    /// Applied to the URLs of items the server reports
+    rewrite: Option<UrlRewrite>,
+    /// Called when the server rejects the token
+    on_unauthorized: Option<Callback<()>>,
+    transport: Rc<dyn Transport>,
+}
+
+impl ApiClient {
+    /// Client for the server at `base`, authenticating with `token` and
+    /// talking to the API over a transport of kind `transport`
+    pub fn new(base: &str, token: Option<String>, transport: TransportKind) -> Self {
+        Self {
+            upload_url: format!("{}/files", base),
+            transport: transport_for(&format!("{}/api", base), token.clone(), transport),
+            token,
+            rewrite: None,
+            on_unauthorized: None,
+        }
+    }
+
+    /// Upload to `url` instead of the server's `/files`
+    pub fn upload_url(self, url: String) -> Self {
+        Self {
+            upload_url: url,
+            ..self
+        }
+    }
+
+    pub fn rewrite(self, rewrite: Option<UrlRewrite>) -> Self {
+        Self { rewrite, ..self }
+    }
+
+    /// Time out and retry requests according to `policy`, reporting
+    /// retries to `on_retry`
+    pub fn policy(self, policy: RequestPolicy, on_retry: Callback<Retry>) -> Self {
+        Self {
+            transport: Rc::new(RetryingTransport::new(
+                self.transport.clone(),
+                policy,
+                Some(on_retry),
+            )),
+            ..self
+        }
+    }
+
+    pub fn on_unauthorized(self, callback: Callback<()>) -> Self {
+        Self {
+            on_unauthorized: Some(callback),
+            ..self
+        }
+    }
+
+    fn media_urls(&self) -> MediaUrls {
+        MediaUrls {
+            rewrite: self.rewrite.clone(),
+        }
+    }
+
+    /// `request`, reporting a rejected token to `on_unauthorized`
+    fn guard<T: 'static>(
+        &self,
+        request: impl std::future::Future<Output = Result<T>> + 'static,
+    ) -> LocalBoxFuture<'static, Result<T>> {
+        let on_unauthorized = self.on_unauthorized;
+        async move {
+            let result = request.await;
+            if let (Err(ClientError::Status(401, _)), Some(callback)) = (&result, on_unauthorized) {
+                callback(())
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+/// Where the UI finds the files of the items the server reports. Media
+/// elements can't set headers, so files are requested with the cookie
+/// logging in sets rather than the token.
+#[derive(Clone)]
+struct MediaUrls {
+    rewrite: Option<UrlRewrite>,
+}
+
+impl MediaUrls {
+    fn apply(&self, item: MediaItem) -> MediaItem {
+        let url = match &self.rewrite {
+            Some(rewrite) => rewrite.apply(&item.url),
+            None => item.url,
+        };
+        MediaItem { url, ..item }
+    }
+}
+
+impl MediaClient for ApiClient {
+    fn get_media(&self, query: MediaQuery) -> LocalBoxFuture<'static, Result<MediaPage>> {
+        let (transport, urls) = (Rc::clone(&self.transport), self.media_urls());
+        self.guard(async move {
+            let page = get_media(transport, query).await?;
+            Ok(MediaPage {
+                items: page
+                    .items
+                    .into_iter()
+                    .map(|item| urls.apply(item))
+                    .collect(),
+                ..page
+            })
+        })
+    }
+
+    fn update_media(
+        &self,
+        id: String,
+        field: MediaField,
+        request_id: String,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        self.guard(update_media(
+            Rc::clone(&self.transport),
+            id,
+            field,
+            request_id,
+        ))
+    }
+
+    fn trash_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        self.guard(trash_media(Rc::clone(&self.transport), id))
+    }
+
+    fn restore_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        self.guard(restore_media(Rc::clone(&self.transport), id))
+    }
+
+    fn delete_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        self.guard(delete_media(Rc::clone(&self.transport), id))
+    }
+
+    fn upload_file(
+        &self,
+        file: web_sys::File,
+        metadata: UploadMetadata,
+        task: UploadTask,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        let (url, token) = (self.upload_url.clone(), self.token.clone());
+        self.guard(async move {
+            let mut pairs = vec![("filename", file.name())];
+            // the tusd hook sorts and catalogues the file by these
+            for (key, value) in [
+                ("title", metadata.title),
+                ("tags", metadata.tags.join(",")),
+                ("folder", metadata.folder),
+                // the same key as other tus clients use
+                ("relativePath", metadata.relative_path),
+            ] {
+                if !value.is_empty() {
+                    pairs.push((key, value))
+                }
+            }
+            tus::upload(&url, token.as_deref(), &file, &pairs, task).await
+        })
+    }
+
+    fn find_by_hash(&self, sha256: String) -> LocalBoxFuture<'static, Result<Option<MediaItem>>> {
+        let (transport, urls) = (Rc::clone(&self.transport), self.media_urls());
+        self.guard(async move {
+            let item = find_by_hash(transport, sha256).await?;
+            Ok(item.map(|item| urls.apply(item)))
+        })
+    }
+
+    fn media_events(&self) -> MediaEvents {
+        let events = self.transport.events(self.on_unauthorized);
+        create_effect(move |_| log!("{:?}", events.events.get()));
+        create_effect(move |_| log!("event stream {}", events.state.get().as_str()));
+        let urls = self.media_urls();
+        MediaEvents {
+            events: Signal::derive(move || {
+                events.events.get().map(|event| match event {
+                    MediaEvent::Created { item } => MediaEvent::Created {
+                        item: urls.apply(item),
+                    },
+                    MediaEvent::Updated { item, request_id } => MediaEvent::Updated {
+                        item: urls.apply(item),
+                        request_id,
+                    },
+                    event => event,
+                })
+            }),
+            state: events.state,
+        }
+    }
+}
+
+/// `GET /media` responds with a bare array when the server doesn't
+/// support pagination
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Listing {
+    Page(MediaPage),
+    All(Vec<MediaItem>),
+}
+
+async fn get_media(transport: Rc<dyn Transport>, query: MediaQuery) -> Result<MediaPage> {
+    let mut request =
+        ApiRequest::new(Method::Get, "/media".to_string()).query("limit", query.limit.to_string());
+    if !query.q.is_empty() {
+        request = request.query("q", query.q);
+    }
+    if let Some(kind) = query.kind {
+        request = request.query("kind", kind.to_string());
+    }
+    if let Some(trashed) = query.trashed {
+        request = request.query("trashed", trashed.to_string());
+    }
+    if let Some(id) = query.id {
+        request = request.query("id", id);
+    }
+    if let Some(cursor) = query.cursor {
+        request = request.query("cursor", cursor);
+    }
+    let body = transport.send(request).await?;
+    Ok(
+        match serde_json::from_str(&body).map_err(|e| ClientError::Decode(e.to_string()))? {
+            Listing::Page(page) => page,
+            Listing::All(items) => MediaPage { items, next: None },
+        },
+    )
+}
+
+async fn update_media(
+    transport: Rc<dyn Transport>,
+    id: String,
+    field: MediaField,
+    request_id: String,
+) -> Result<()> {
+    field.validate().map_err(ClientError::Invalid)?;
+    let request = ApiRequest::new(Method::Patch, format!("/media/{}", id))
+        .query("f", field.name())
+        .query("v", field.value())
+        .query("rid", request_id);
+    transport.send(request).await?;
+    Ok(())
+}
+
+async fn trash_media(transport: Rc<dyn Transport>, id: String) -> Result<()> {
+    let request = ApiRequest::new(Method::Delete, format!("/media/{}", id));
+    transport.send(request).await?;
+    Ok(())
+}
+
+async fn restore_media(transport: Rc<dyn Transport>, id: String) -> Result<()> {
+    let request = ApiRequest::new(Method::Post, format!("/media/{}/restore", id));
+    transport.send(request).await?;
+    Ok(())
+}
+
+async fn delete_media(transport: Rc<dyn Transport>, id: String) -> Result<()> {
+    // the server removes the files before responding, and a repeat after
+    // the first attempt did so would fail with 404
+    let request = ApiRequest::new(Method::Delete, format!("/media/{}", id))
+        .query("permanent", "true")
+        .timeout(Duration::from_secs(60))
+        .no_retry();
+    transport.send(request).await?;
+    Ok(())
+}
+
+async fn find_by_hash(transport: Rc<dyn Transport>, sha256: String) -> Result<Option<MediaItem>> {
+    let request = ApiRequest::new(Method::Get, format!("/hashes/{}", sha256));
+    match transport.send(request).await {
+        Ok(body) => serde_json::from_str(&body)
+            .map(Some)
+            .map_err(|e| ClientError::Decode(e.to_string())),
+        // also what servers without hashes answer
+        Err(ClientError::Status(404, _)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Credentials<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+/// Exchange a username and password for a token with the server at `base`.
+/// Always uses plain HTTP, since WebSocket connections need the cookie the
+/// response sets to be opened.
+pub async fn login(base: &str, username: &str, password: &str) -> Result<String> {
+    let response = gloo_net::http::Request::post(&format!("{}/api/login", base))
+        .json(&Credentials { username, password })?
+        .send()
+        .await?;
+    if !response.ok() {
+        return Err(ClientError::Status(
+            response.status(),
+            response.text().await?,
+        ));
+    }
+    Ok(response.json::<LoginResponse>().await?.token)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::Engine;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::client::{stand_in, UploadPolicy};
+
+    #[wasm_bindgen_test]
+    async fn logs_in_with_a_password() {
+        stand_in::reset();
+        stand_in::respond("POST", "/api/login", 200, r#"{"token": "secret"}"#);
+        assert_eq!(
+            login(stand_in::BASE, "admin", "hunter2").await,
+            Ok("secret".to_string())
+        );
+        let requests = stand_in::requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&requests[0].body).unwrap(),
+            serde_json::json!({"username": "admin", "password": "hunter2"})
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn reports_a_wrong_password() {
+        stand_in::reset();
+        stand_in::respond("POST", "/api/login", 401, "wrong username or password");
+        assert_eq!(
+            login(stand_in::BASE, "admin", "guess").await,
+            Err(ClientError::Status(
+                401,
+                "wrong username or password".to_string()
+            ))
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn deletes_for_good_only_once() {
+        stand_in::reset();
+        stand_in::respond("DELETE", "/api/media/1", 503, "");
+        let client = ApiClient::new(stand_in::BASE, None, TransportKind::Rest)
+            .policy(RequestPolicy::default(), Callback::new(|_| {}));
+        assert!(client.delete_media("1".to_string()).await.is_err());
+        assert_eq!(stand_in::requests().len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    async fn uploads_with_the_token() {
+        stand_in::reset();
+        stand_in::respond_with(
+            "POST",
+            "/files",
+            201,
+            "",
+            &[("Location", "/files/1")],
+            Duration::ZERO,
+        );
+        stand_in::respond_with(
+            "PATCH",
+            "/files/1",
+            204,
+            "",
+            &[("Upload-Offset", "5")],
+            Duration::ZERO,
+        );
+        let parts = js_sys::Array::of1(&"hello".into());
+        let file = web_sys::File::new_with_str_sequence(&parts, "hello.txt").unwrap();
+        let task = UploadTask::new(file.size(), Signal::derive(UploadPolicy::default));
+        let client = ApiClient::new(
+            stand_in::BASE,
+            Some("secret".to_string()),
+            TransportKind::Rest,
+        );
+        let uploaded = client
+            .upload_file(file, UploadMetadata::new("Hello", "", ""), task)
+            .await;
+        assert_eq!(uploaded, Ok(()));
+        assert_eq!(task.sent.get_untracked(), 5.0);
+
+        let requests = stand_in::requests();
+        let methods = requests
+            .iter()
+            .map(|r| r.method.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(methods, vec!["POST", "PATCH"]);
+        for request in &requests {
+            assert_eq!(
+                request.headers.get("authorization").map(String::as_str),
+                Some("Bearer secret")
+            );
+        }
+        let encode = |v: &str| base64::prelude::BASE64_STANDARD.encode(v);
+        assert_eq!(
+            requests[0].headers.get("upload-metadata"),
+            Some(&format!(
+                "filename {},title {}",
+                encode("hello.txt"),
+                encode("Hello")
+            ))
+        );
+        assert_eq!(requests[1].body, "hello");
+    }
+}
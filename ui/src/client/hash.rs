@@ -0,0 +1,32 @@
+//! Content hashes of files the user picks, so that uploads of files the
+//! library already has can be skipped.
+
+use sha2::{Digest, Sha256};
+use wasm_bindgen_futures::JsFuture;
+
+use super::{ClientError, Result};
+
+/// Read files this many bytes at a time, so big ones don't have to fit in
+/// memory
+const CHUNK_SIZE: f64 = 8_000_000.0;
+
+/// SHA-256 of `file` as lowercase hex. `progress` is told how many bytes
+/// have been hashed after each chunk.
+pub async fn sha256(file: &web_sys::File, progress: impl Fn(f64)) -> Result<String> {
+    let unreadable = |e| ClientError::Invalid(format!("couldn't read {}: {:?}", file.name(), e));
+    let mut hasher = Sha256::new();
+    let mut offset = 0.0;
+    while offset < file.size() {
+        let end = (offset + CHUNK_SIZE).min(file.size());
+        let chunk = file
+            .slice_with_f64_and_f64(offset, end)
+            .map_err(unreadable)?;
+        let buffer = JsFuture::from(chunk.array_buffer())
+            .await
+            .map_err(unreadable)?;
+        hasher.update(js_sys::Uint8Array::new(&buffer).to_vec());
+        offset = end;
+        progress(offset)
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,216 @@
+//! Browse several remotes as one library.
+//!
+//! Item ids are prefixed with the id of the remote they come from, e.g.
+//! `nas:42`, so that requests about an item can be routed back to it.
+
+use std::collections::HashMap;
+
+use futures::{future::LocalBoxFuture, FutureExt};
+use leptos::*;
+
+use super::{
+    Client, ClientError, ConnectionState, MediaClient, MediaEvents, Result, UploadMetadata,
+    UploadTask,
+};
+use crate::{
+    data::{MediaEvent, MediaField, MediaItem, MediaPage, MediaQuery},
+    log,
+};
+
+pub struct MergedClient {
+    /// Clients by remote id, in display order
+    remotes: Vec<(String, Client)>,
+}
+
+impl MergedClient {
+    pub fn new(remotes: Vec<(String, Client)>) -> Self {
+        Self { remotes }
+    }
+
+    /// Client of the remote that item `id` belongs to, and the item's id
+    /// on that remote
+    fn route(&self, id: &str) -> Result<(Client, String)> {
+        let (remote, id) = split(id)?;
+        match self.remotes.iter().find(|(r, _)| r == remote) {
+            Some((_, client)) => Ok((client.clone(), id.to_string())),
+            None => Err(ClientError::Invalid(format!("unknown remote {:?}", remote))),
+        }
+    }
+}
+
+fn split(id: &str) -> Result<(&str, &str)> {
+    id.split_once(':')
+        .ok_or_else(|| ClientError::Invalid(format!("{:?} doesn't name a remote", id)))
+}
+
+fn prefixed(remote: &str, item: MediaItem) -> MediaItem {
+    MediaItem {
+        id: format!("{}:{}", remote, item.id),
+        ..item
+    }
+}
+
+impl MediaClient for MergedClient {
+    fn get_media(&self, query: MediaQuery) -> LocalBoxFuture<'static, Result<MediaPage>> {
+        get_media(self.remotes.clone(), query).boxed_local()
+    }
+
+    fn update_media(
+        &self,
+        id: String,
+        field: MediaField,
+        request_id: String,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        match self.route(&id) {
+            Ok((client, id)) => client.update_media(id, field, request_id),
+            Err(e) => async move { Err(e) }.boxed_local(),
+        }
+    }
+
+    fn trash_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        match self.route(&id) {
+            Ok((client, id)) => client.trash_media(id),
+            Err(e) => async move { Err(e) }.boxed_local(),
+        }
+    }
+
+    fn restore_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        match self.route(&id) {
+            Ok((client, id)) => client.restore_media(id),
+            Err(e) => async move { Err(e) }.boxed_local(),
+        }
+    }
+
+    fn delete_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        match self.route(&id) {
+            Ok((client, id)) => client.delete_media(id),
+            Err(e) => async move { Err(e) }.boxed_local(),
+        }
+    }
+
+    /// Uploads go to the first remote
+    fn upload_file(
+        &self,
+        file: web_sys::File,
+        metadata: UploadMetadata,
+        task: UploadTask,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        match self.remotes.first() {
+            Some((_, client)) => client.upload_file(file, metadata, task),
+            None => {
+                async move { Err(ClientError::Invalid("no remotes".to_string())) }.boxed_local()
+            }
+        }
+    }
+
+    /// Only the first remote is asked, since that is where uploads go
+    fn find_by_hash(&self, sha256: String) -> LocalBoxFuture<'static, Result<Option<MediaItem>>> {
+        let Some((remote, client)) = self.remotes.first() else {
+            return async move { Ok(None) }.boxed_local();
+        };
+        let (remote, item) = (remote.clone(), client.find_by_hash(sha256));
+        async move { Ok(item.await?.map(|item| prefixed(&remote, item))) }.boxed_local()
+    }
+
+    fn media_events(&self) -> MediaEvents {
+        let (event, set_event) = create_signal(None::<MediaEvent>);
+        let states = self
+            .remotes
+            .iter()
+            .map(|(remote, client)| {
+                let events = client.media_events();
+                let remote = remote.clone();
+                create_effect(move |_| {
+                    let Some(event) = events.events.get() else {
+                        return;
+                    };
+                    set_event.set(Some(match event {
+                        MediaEvent::Created { item } => MediaEvent::Created {
+                            item: prefixed(&remote, item),
+                        },
+                        MediaEvent::Updated { item, request_id } => MediaEvent::Updated {
+                            item: prefixed(&remote, item),
+                            request_id,
+                        },
+                        MediaEvent::Removed { id } => MediaEvent::Removed {
+                            id: format!("{}:{}", remote, id),
+                        },
+                        MediaEvent::Resync => MediaEvent::Resync,
+                    }))
+                });
+                events.state
+            })
+            .collect::<Vec<_>>();
+        let state = Signal::derive(move || {
+            let states = states.iter().map(|s| s.get()).collect::<Vec<_>>();
+            if states.iter().all(|s| *s == ConnectionState::Online) {
+                ConnectionState::Online
+            } else if states.iter().all(|s| *s == ConnectionState::Offline) {
+                ConnectionState::Offline
+            } else {
+                ConnectionState::Reconnecting
+            }
+        });
+        MediaEvents {
+            events: event.into(),
+            state,
+        }
+    }
+}
+
+/// Query every remote and combine their pages. The merged cursor maps
+/// each remote that has more items to its own cursor. Remotes that fail
+/// are left out unless all of them fail.
+async fn get_media(remotes: Vec<(String, Client)>, query: MediaQuery) -> Result<MediaPage> {
+    let cursors = match &query.cursor {
+        Some(cursor) => Some(
+            serde_json::from_str::<HashMap<String, String>>(cursor)
+                .map_err(|e| ClientError::Invalid(format!("bad cursor: {}", e)))?,
+        ),
+        None => None,
+    };
+    let target = query.id.as_deref().map(split).transpose()?;
+    let requests = remotes.into_iter().filter_map(|(remote, client)| {
+        let mut q = query.clone();
+        if let Some(cursors) = &cursors {
+            q.cursor = Some(cursors.get(&remote)?.clone());
+        }
+        if let Some((target, id)) = target {
+            if target != remote {
+                return None;
+            }
+            q.id = Some(id.to_string());
+        }
+        Some(async move { (remote, client.get_media(q).await) })
+    });
+    let mut page = MediaPage {
+        items: Vec::new(),
+        next: None,
+    };
+    let mut next = HashMap::new();
+    let mut error = None;
+    let mut any_ok = false;
+    for (remote, result) in futures::future::join_all(requests).await {
+        match result {
+            Ok(p) => {
+                any_ok = true;
+                page.items
+                    .extend(p.items.into_iter().map(|item| prefixed(&remote, item)));
+                if let Some(cursor) = p.next {
+                    next.insert(remote, cursor);
+                }
+            }
+            Err(e) => {
+                log!("failed to load media from {}: {}", remote, e);
+                error = Some(e)
+            }
+        }
+    }
+    if let (false, Some(e)) = (any_ok, error) {
+        return Err(e);
+    }
+    if !next.is_empty() {
+        page.next = Some(serde_json::to_string(&next).expect("cursors serialize"));
+    }
+    Ok(page)
+}
@@ -1,10 +1,74 @@
 //! Generate fake data for faster debugging cycles.
 
-use crate::{data::MediaItem, log};
-use std::{array::IntoIter, sync::Mutex};
+use super::{
+    ClientError, ConnectionState, MediaClient, MediaEvents, Result, UploadMetadata, UploadState,
+    UploadTask,
+};
+use crate::data::{MediaEvent, MediaField, MediaItem, MediaPage, MediaQuery};
+use futures::{future::LocalBoxFuture, FutureExt};
+use gloo_timers::future::TimeoutFuture;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, collections::BTreeMap, sync::Mutex};
+
+static MEDIA: Mutex<Option<Vec<MediaItem>>> = Mutex::new(None);
+/// Ids of uploaded items by the SHA-256 of their file
+static HASHES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Where the latest event stream announces changes made by uploads
+    static EVENTS: Cell<Option<WriteSignal<Option<MediaEvent>>>> = const { Cell::new(None) };
+}
+
+/// Client that serves fake data without an API server
+pub struct MockClient;
+
+impl MediaClient for MockClient {
+    fn get_media(&self, query: MediaQuery) -> LocalBoxFuture<'static, Result<MediaPage>> {
+        get_media(query).boxed_local()
+    }
+
+    fn update_media(
+        &self,
+        id: String,
+        field: MediaField,
+        _request_id: String,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        update_media(id, field).boxed_local()
+    }
+
+    fn trash_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        trash_media(id).boxed_local()
+    }
+
+    fn restore_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        restore_media(id).boxed_local()
+    }
+
+    fn delete_media(&self, id: String) -> LocalBoxFuture<'static, Result<()>> {
+        delete_media(id).boxed_local()
+    }
+
+    fn upload_file(
+        &self,
+        file: web_sys::File,
+        metadata: UploadMetadata,
+        task: UploadTask,
+    ) -> LocalBoxFuture<'static, Result<()>> {
+        upload_file(file, metadata, task).boxed_local()
+    }
+
+    fn find_by_hash(&self, sha256: String) -> LocalBoxFuture<'static, Result<Option<MediaItem>>> {
+        find_by_hash(sha256).boxed_local()
+    }
+
+    fn media_events(&self) -> MediaEvents {
+        media_events()
+    }
 
-lazy_static::lazy_static! {
-    static ref MEDIA: Mutex<Option<Vec<MediaItem>>> = Mutex::new(None);
+    fn is_demo(&self) -> bool {
+        true
+    }
 }
 
 fn init_media() -> Option<Vec<MediaItem>> {
@@ -15,6 +79,11 @@ fn init_media() -> Option<Vec<MediaItem>> {
             title: format!("Blah {}", i),
             format: "webp".to_string(),
             url: format!("https://www.gstatic.com/webp/gallery/{}.webp", i),
+            width: Some(550),
+            height: Some(368),
+            size: Some(30_000 + 1_000 * i),
+            added: Some(format!("2024-05-{:02}T12:00:00Z", i)),
+            ..Default::default()
         });
     }
     for i in 0..5 {
@@ -25,66 +94,252 @@ fn init_media() -> Option<Vec<MediaItem>> {
             title,
             format: "webm".to_string(),
             url: "https://dl6.webmfiles.org/big-buck-bunny_trailer.webm".to_owned(),
+            ..big_buck_bunny_info()
         });
     }
     return Some(m);
 }
 
-pub async fn get_media() -> Vec<MediaItem> {
+/// Technical metadata for the Big Buck Bunny trailer
+fn big_buck_bunny_info() -> MediaItem {
+    MediaItem {
+        duration: Some(32.48),
+        width: Some(640),
+        height: Some(360),
+        video_codec: Some("vp8".to_string()),
+        audio_codec: Some("vorbis".to_string()),
+        bitrate: Some(533_000),
+        size: Some(2_165_175),
+        added: Some("2024-05-10T12:00:00Z".to_string()),
+        ..Default::default()
+    }
+}
+
+async fn get_media(query: MediaQuery) -> Result<MediaPage> {
+    let mut media = MEDIA.lock().unwrap();
+    if media.is_none() {
+        *media = init_media()
+    }
+    let mut items = media
+        .as_ref()
+        .unwrap()
+        .iter()
+        .filter(|item| query.matches(item))
+        .cloned()
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.id.cmp(&b.id)));
+    // like the API, pages pick up after the last item of the previous one
+    if let Some(cursor) = query.cursor {
+        let after = serde_json::from_str::<PageCursor>(&cursor)
+            .map_err(|_| ClientError::Status(400, format!("invalid cursor: {}", cursor)))?;
+        items.retain(|item| (&item.title, &item.id) > (&after.title, &after.id));
+    }
+    let next = (0 < query.limit && query.limit < items.len()).then(|| {
+        let last = &items[query.limit - 1];
+        serde_json::to_string(&PageCursor {
+            title: last.title.clone(),
+            id: last.id.clone(),
+        })
+        .expect("cursors serialize")
+    });
+    items.truncate(query.limit);
+    Ok(MediaPage { items, next })
+}
+
+/// Title and id of the last item of a page
+#[derive(Serialize, Deserialize)]
+struct PageCursor {
+    title: String,
+    id: String,
+}
+
+async fn update_media(id: String, field: MediaField) -> Result<()> {
+    field.validate().map_err(ClientError::Invalid)?;
+    let mut media = MEDIA.lock().unwrap();
+    if media.is_none() {
+        *media = init_media()
+    }
+    match media.as_mut().unwrap().iter_mut().find(|item| item.id == id) {
+        Some(item) => {
+            item.update(field);
+            Ok(())
+        }
+        None => Err(ClientError::Status(404, "no such item".to_string())),
+    }
+}
+
+async fn set_trashed(id: String, trashed: bool) -> Result<()> {
     let mut media = MEDIA.lock().unwrap();
     if media.is_none() {
         *media = init_media()
     }
-    return media.clone().unwrap();
+    match media.as_mut().unwrap().iter_mut().find(|item| item.id == id) {
+        Some(item) => {
+            item.trashed = trashed;
+            Ok(())
+        }
+        None => Err(ClientError::Status(404, "no such item".to_string())),
+    }
 }
 
-pub async fn update_media(id: String, field: String, value: String) -> anyhow::Result<bool> {
+async fn trash_media(id: String) -> Result<()> {
+    set_trashed(id, true).await
+}
+
+async fn restore_media(id: String) -> Result<()> {
+    set_trashed(id, false).await
+}
+
+async fn delete_media(id: String) -> Result<()> {
     let mut media = MEDIA.lock().unwrap();
     if media.is_none() {
         *media = init_media()
     }
     let v = media.as_mut().unwrap();
-    for item in v.iter_mut() {
-        if item.id == id {
-            match field.as_str() {
-                "title" => item.title = value.to_string(),
-                "format" => item.format = value.to_string(),
-                _ => log!("unknown field for Video: {}", field),
-            }
+    match v.iter().position(|item| item.id == id) {
+        Some(i) if v[i].trashed => {
+            v.remove(i);
+            Ok(())
         }
+        Some(_) => Err(ClientError::Status(
+            409,
+            "only items in the trash can be deleted".to_string(),
+        )),
+        None => Err(ClientError::Status(404, "no such item".to_string())),
     }
-    Ok(true)
 }
 
-pub async fn upload_file(_file: web_sys::File) {
-    log!("File uploads not supported in demo mode!");
+/// Pretend to send `file` in chunks, then add it to the demo library. The
+/// item points at the file in the browser, so it only lasts as long as the
+/// page. There are no folders in the demo library.
+async fn upload_file(
+    file: web_sys::File,
+    metadata: UploadMetadata,
+    task: UploadTask,
+) -> Result<()> {
+    const CHUNKS: u32 = 20;
+    const CHUNK_MS: u32 = 150;
+    let mut sent = task.sent.get_untracked();
+    while sent < task.size {
+        match task.state.get_untracked() {
+            UploadState::Paused => {}
+            UploadState::Cancelled => return Ok(()),
+            _ => {
+                let chunk = (task.size / CHUNKS as f64).max(1.0).min(task.size - sent);
+                sent += chunk;
+                task.progress(sent, chunk, CHUNK_MS as f64 / 1000.0);
+            }
+        }
+        TimeoutFuture::new(CHUNK_MS).await;
+    }
+    let url = web_sys::Url::create_object_url_with_blob(&file)
+        .map_err(|e| ClientError::Invalid(format!("{:?}", e)))?;
+    let name = file.name();
+    let (title, format) = match name.rsplit_once('.') {
+        Some((title, format)) => (title.to_string(), format.to_lowercase()),
+        None => (name.clone(), String::new()),
+    };
+    let title = Some(metadata.title)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(title);
+    let item = MediaItem {
+        id: uuid::Uuid::new_v4().to_string(),
+        url,
+        title,
+        format,
+        size: Some(file.size() as u64),
+        tags: metadata.tags,
+        added: Some(js_sys::Date::new_0().to_iso_string().into()),
+        ..Default::default()
+    };
+    if let Some(sha256) = task.sha256.get_untracked() {
+        HASHES.lock().unwrap().insert(sha256, item.id.clone());
+    }
+    let event = MediaEvent::Created { item };
+    apply(&event);
+    if let Some(set_event) = EVENTS.get() {
+        set_event.set(Some(event))
+    }
+    Ok(())
 }
 
-use leptos::*;
+/// Only uploaded items are known by hash, the demo files aren't local
+async fn find_by_hash(sha256: String) -> Result<Option<MediaItem>> {
+    let Some(id) = HASHES.lock().unwrap().get(&sha256).cloned() else {
+        return Ok(None);
+    };
+    let media = MEDIA.lock().unwrap();
+    Ok(media
+        .as_ref()
+        .and_then(|v| v.iter().find(|i| i.id == id).cloned()))
+}
 
-pub fn new_media() -> Signal<Option<MediaItem>> {
-    let (data, set_data) = create_signal(None::<MediaItem>);
-    let interval = leptos_use::use_interval(10_000);
-    create_effect(move |items| {
-        (interval.counter).track();
-        let mut items = if items.is_none() {
-            [1, 2, 3, 4].into_iter()
-        } else {
-            items.unwrap()
-        };
-        if let Some(i) = items.next() {
-            let id = (12 + i).to_string();
-            let title = format!("Big Buck Bunny {}", id);
-            set_data(Some(MediaItem {
+/// Changes that happen to the demo library while the app is open
+fn demo_events() -> Vec<MediaEvent> {
+    let mut events = Vec::new();
+    for i in 1..5 {
+        let id = (12 + i).to_string();
+        let title = format!("Big Buck Bunny {}", id);
+        events.push(MediaEvent::Created {
+            item: MediaItem {
                 id,
                 title,
                 format: "webm".to_string(),
                 url: "https://dl6.webmfiles.org/big-buck-bunny_trailer.webm".to_owned(),
-            }));
+                ..big_buck_bunny_info()
+            },
+        });
+    }
+    if let MediaEvent::Created { item } = &events[0] {
+        events.push(MediaEvent::Updated {
+            item: MediaItem {
+                title: format!("{} (renamed elsewhere)", item.title),
+                ..item.clone()
+            },
+            request_id: None,
+        });
+    }
+    events.push(MediaEvent::Removed {
+        id: "14".to_string(),
+    });
+    events
+}
+
+/// Apply `event` to the demo library so that later requests agree with it
+fn apply(event: &MediaEvent) {
+    let mut media = MEDIA.lock().unwrap();
+    if media.is_none() {
+        *media = init_media()
+    }
+    let v = media.as_mut().unwrap();
+    match event {
+        MediaEvent::Created { item } | MediaEvent::Updated { item, .. } => {
+            v.retain(|i| i.id != item.id);
+            v.push(item.clone());
+        }
+        MediaEvent::Removed { id } => v.retain(|i| &i.id != id),
+        MediaEvent::Resync => {}
+    }
+}
+
+fn media_events() -> MediaEvents {
+    let (data, set_data) = create_signal(None::<MediaEvent>);
+    EVENTS.set(Some(set_data));
+    let interval = leptos_use::use_interval(10_000);
+    create_effect(move |events| {
+        (interval.counter).track();
+        let mut events = events.unwrap_or_else(|| demo_events().into_iter());
+        if let Some(event) = events.next() {
+            apply(&event);
+            set_data(Some(event));
         } else {
             (interval.pause)()
         }
-        items
+        events
     });
-    return data.into();
+    MediaEvents {
+        events: data.into(),
+        // there is no connection to lose
+        state: Signal::derive(|| ConnectionState::Online),
+    }
 }
@@ -0,0 +1,186 @@
+// Stand-in for the API in tests. Takes the place of fetch, EventSource and
+// WebSocket in the page, answers from canned responses and records what
+// it was sent.
+
+const state = {
+    up: true,
+    // event streams and sockets are turned away, as without a valid login
+    refused: false,
+    // `${method} ${pathname}` => { status, body, headers, delay }
+    responses: new Map(),
+    requests: [],
+    connections: [],
+    sources: [],
+    sockets: [],
+};
+
+function response_to(method, pathname) {
+    return state.responses.get(`${method} ${pathname}`) ?? { status: 404, body: "", headers: {}, delay: 0 };
+}
+
+function later(delay, f) {
+    setTimeout(f, delay);
+}
+
+async function fetch(input, init) {
+    const request = input instanceof Request && init === undefined ? input : new Request(input, init);
+    const headers = {};
+    request.headers.forEach((value, name) => (headers[name] = value));
+    const url = new URL(request.url);
+    const received = {
+        method: request.method,
+        path: url.pathname,
+        query: url.search.replace(/^\?/, ""),
+        headers,
+        body: await request.text(),
+        aborted: false,
+    };
+    state.requests.push(received);
+    if (!state.up) {
+        throw new TypeError("Failed to fetch");
+    }
+    const { status, body, headers: response_headers, delay } = response_to(request.method, url.pathname);
+    await new Promise((resolve, reject) => {
+        later(delay, resolve);
+        request.signal.addEventListener("abort", () => {
+            received.aborted = true;
+            reject(new DOMException("the request was aborted", "AbortError"));
+        });
+    });
+    const empty = [101, 204, 205, 304].includes(status);
+    return new Response(empty ? null : body, { status, headers: response_headers });
+}
+
+class EventSource {
+    constructor(url) {
+        this.url = url;
+        this.readyState = 0;
+        state.connections.push(url);
+        state.sources.push(this);
+        later(0, () => {
+            if (this.readyState === 2) {
+                return;
+            } else if (state.up && !state.refused) {
+                this.readyState = 1;
+                this.onopen?.(new Event("open"));
+            } else {
+                this.hang_up();
+            }
+        });
+    }
+
+    close() {
+        this.readyState = 2;
+    }
+
+    hang_up() {
+        this.readyState = 2;
+        this.onerror?.(new Event("error"));
+    }
+}
+
+class WebSocket {
+    constructor(url) {
+        this.url = url;
+        this.readyState = 0;
+        // requests are relative to the API root the socket lives under
+        this.root = new URL(url).pathname.replace(/\/ws$/, "");
+        state.connections.push(url);
+        state.sockets.push(this);
+        later(0, () => {
+            if (this.readyState === 3) {
+                return;
+            } else if (state.up && !state.refused) {
+                this.readyState = 1;
+                this.onopen?.(new Event("open"));
+            } else {
+                this.hang_up();
+            }
+        });
+    }
+
+    send(message) {
+        if (this.readyState !== 1) {
+            throw new DOMException("the socket isn't open", "InvalidStateError");
+        }
+        const request = JSON.parse(message);
+        state.requests.push({
+            method: request.method,
+            path: this.root + request.path,
+            query: request.query,
+            headers: {},
+            body: "",
+        });
+        const { status, body, delay } = response_to(request.method, this.root + request.path);
+        later(delay, () => this.receive({ type: "response", id: request.id, status, body }));
+    }
+
+    receive(message) {
+        if (this.readyState === 1) {
+            this.onmessage?.(new MessageEvent("message", { data: JSON.stringify(message) }));
+        }
+    }
+
+    close() {
+        this.readyState = 3;
+    }
+
+    hang_up() {
+        this.readyState = 3;
+        this.onclose?.(new CloseEvent("close"));
+    }
+}
+
+export function reset() {
+    for (const connection of [...state.sources, ...state.sockets]) {
+        connection.close();
+    }
+    Object.assign(state, {
+        up: true,
+        refused: false,
+        responses: new Map(),
+        requests: [],
+        connections: [],
+        sources: [],
+        sockets: [],
+    });
+    Object.assign(globalThis, { fetch, EventSource, WebSocket });
+}
+
+export function respond(method, pathname, status, body, headers, delay) {
+    state.responses.set(`${method} ${pathname}`, { status, body, headers: JSON.parse(headers), delay });
+}
+
+export function requests() {
+    return JSON.stringify(state.requests);
+}
+
+export function connections() {
+    return JSON.stringify(state.connections);
+}
+
+export function set_up(up) {
+    state.up = up;
+}
+
+export function set_refused(refused) {
+    state.refused = refused;
+}
+
+export function push_event(id, data) {
+    for (const source of state.sources.filter((s) => s.readyState === 1)) {
+        source.onmessage?.(new MessageEvent("message", { data, lastEventId: id }));
+    }
+    for (const socket of state.sockets) {
+        socket.receive({ type: "event", id, data: JSON.parse(data) });
+    }
+}
+
+export function disconnect() {
+    for (const source of state.sources.filter((s) => s.readyState !== 2)) {
+        source.hang_up();
+    }
+    for (const socket of state.sockets.filter((s) => s.readyState !== 3)) {
+        socket.hang_up();
+    }
+}
@@ -0,0 +1,128 @@
+//! A stand-in for the API for tests, living in the test page itself. It
+//! takes the place of `fetch`, `EventSource` and `WebSocket`, answers with
+//! canned responses and records what the client sent.
+
+use std::{collections::HashMap, time::Duration};
+
+use gloo_timers::future::TimeoutFuture;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+/// Where tests pretend the server is
+pub const BASE: &str = "http://stand-in.test";
+
+mod js {
+    use super::*;
+
+    #[wasm_bindgen(module = "/src/client/stand_in.js")]
+    extern "C" {
+        pub fn reset();
+        pub fn respond(
+            method: &str,
+            path: &str,
+            status: u16,
+            body: &str,
+            headers: &str,
+            delay: u32,
+        );
+        pub fn requests() -> String;
+        pub fn connections() -> String;
+        pub fn set_up(up: bool);
+        pub fn set_refused(refused: bool);
+        pub fn push_event(id: &str, data: &str);
+        pub fn disconnect();
+    }
+}
+
+/// A request the stand-in received, over HTTP or a WebSocket
+#[derive(Debug, Clone, Deserialize)]
+pub struct Received {
+    pub method: String,
+    /// Including the API root, e.g. `/api/media`
+    pub path: String,
+    pub query: String,
+    /// By lowercase name. Empty for requests sent over a WebSocket.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    /// Whether the client gave up on the request before it was answered
+    #[serde(default)]
+    pub aborted: bool,
+}
+
+/// Start from a server that is up and answers every request with 404
+pub fn reset() {
+    js::reset()
+}
+
+/// Answer `method` requests to `path` with `status` and `body`
+pub fn respond(method: &str, path: &str, status: u16, body: &str) {
+    respond_with(method, path, status, body, &[], Duration::ZERO)
+}
+
+/// Answer `method` requests to `path` with `status`, `body` and `headers`
+/// after `delay`
+pub fn respond_with(
+    method: &str,
+    path: &str,
+    status: u16,
+    body: &str,
+    headers: &[(&str, &str)],
+    delay: Duration,
+) {
+    let headers = serde_json::to_string(&headers.iter().cloned().collect::<HashMap<_, _>>())
+        .expect("headers are strings");
+    js::respond(
+        method,
+        path,
+        status,
+        body,
+        &headers,
+        delay.as_millis() as u32,
+    )
+}
+
+/// Requests received so far, oldest first
+pub fn requests() -> Vec<Received> {
+    serde_json::from_str(&js::requests()).expect("stand-in records valid JSON")
+}
+
+/// URLs of the event streams and WebSockets opened so far, oldest first
+pub fn connections() -> Vec<String> {
+    serde_json::from_str(&js::connections()).expect("stand-in records valid JSON")
+}
+
+/// Whether the server accepts requests and connections
+pub fn set_up(up: bool) {
+    js::set_up(up)
+}
+
+/// Whether the server turns away event streams and sockets while still
+/// answering requests, as it does when they come without a valid login
+pub fn set_refused(refused: bool) {
+    js::set_refused(refused)
+}
+
+/// Send a media event with id `id` to every open event stream and socket
+pub fn push_event(id: &str, event: serde_json::Value) {
+    js::push_event(id, &event.to_string())
+}
+
+/// Drop every open event stream and socket
+pub fn disconnect() {
+    js::disconnect()
+}
+
+/// Wait for `done` to hold, checking every few milliseconds. Reconnects
+/// back off for up to a second, so this waits a good while longer before
+/// failing the test.
+pub async fn until(what: &str, done: impl Fn() -> bool) {
+    for _ in 0..300 {
+        if done() {
+            return;
+        }
+        TimeoutFuture::new(10).await
+    }
+    panic!("timed out waiting for {}", what)
+}
@@ -0,0 +1,201 @@
+//! Ways of carrying API requests and media events to and from the server
+
+use std::{rc::Rc, time::Duration};
+
+use futures::future::LocalBoxFuture;
+use leptos::Callback;
+use serde::{Deserialize, Serialize};
+
+use super::{MediaEvents, Result};
+
+mod rest;
+mod retry;
+mod sse;
+mod ws;
+
+pub use rest::RestTransport;
+pub use retry::{RequestPolicy, Retry, RetryingTransport};
+pub use ws::WsTransport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Post => "POST",
+            Self::Patch => "PATCH",
+            Self::Delete => "DELETE",
+        }
+    }
+
+    /// Whether sending a request twice has the same effect as sending it
+    /// once, making it safe to retry
+    pub fn is_idempotent(&self) -> bool {
+        matches!(self, Self::Get | Self::Delete)
+    }
+}
+
+/// A request to the API, independent of how it is carried
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub method: Method,
+    /// Path below the API root, e.g. `/media/1`
+    pub path: String,
+    pub query: Vec<(&'static str, String)>,
+    /// Overrides the [`RequestPolicy`] timeout
+    pub timeout: Option<Duration>,
+    /// Whether a failed attempt may be repeated. Defaults to whether the
+    /// method is idempotent.
+    pub retry: bool,
+    /// Gives up on the request when aborted, e.g. once it timed out
+    pub abort: Option<web_sys::AbortSignal>,
+}
+
+impl ApiRequest {
+    pub fn new(method: Method, path: String) -> Self {
+        Self {
+            method,
+            path,
+            query: Vec::new(),
+            timeout: None,
+            retry: method.is_idempotent(),
+            abort: None,
+        }
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Never repeat the request, e.g. because a repeat would fail where the
+    /// first attempt succeeded
+    pub fn no_retry(self) -> Self {
+        Self {
+            retry: false,
+            ..self
+        }
+    }
+
+    pub fn abort(self, signal: web_sys::AbortSignal) -> Self {
+        Self {
+            abort: Some(signal),
+            ..self
+        }
+    }
+
+    pub fn query(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.query.push((name, value.into()));
+        self
+    }
+
+    /// Query parameters in `application/x-www-form-urlencoded` form
+    pub fn query_string(&self) -> String {
+        self.query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, js_sys::encode_uri_component(value)))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+/// Connection to the API that requests and media events travel over
+pub trait Transport {
+    /// Send `request` and resolve to the response body. Responses with a
+    /// non-success status are errors. Dropping the future gives up on the
+    /// request; transports that can cancel it in flight also do so when
+    /// its `abort` signal fires.
+    fn send(&self, request: ApiRequest) -> LocalBoxFuture<'static, Result<String>>;
+
+    /// Stream of changes to the library. Must be called within a
+    /// reactive owner, which also owns the subscription. Stops
+    /// reconnecting and calls `on_unauthorized` once the server turns
+    /// out to have rejected the login.
+    fn events(&self, on_unauthorized: Option<Callback<()>>) -> MediaEvents;
+}
+
+/// Ways the UI can talk to the API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// REST requests with server-sent events
+    #[default]
+    Rest,
+    /// Requests and events over a single WebSocket
+    Ws,
+}
+
+impl TransportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rest => "rest",
+            Self::Ws => "ws",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "rest" => Some(Self::Rest),
+            "ws" => Some(Self::Ws),
+            _ => None,
+        }
+    }
+}
+
+/// Transport of kind `kind` for the API at root URL `base`. `token` is
+/// sent along with every HTTP request; event streams and WebSockets can't
+/// set headers and are opened with the cookie logging in sets.
+pub fn transport_for(base: &str, token: Option<String>, kind: TransportKind) -> Rc<dyn Transport> {
+    match kind {
+        TransportKind::Ws => Rc::new(WsTransport::new(base)),
+        TransportKind::Rest => Rc::new(RestTransport::new(base, token)),
+    }
+}
+
+/// `url` with query parameter `name` set to `value`
+fn with_param(url: &str, name: &str, value: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}{}={}",
+        url,
+        separator,
+        name,
+        js_sys::encode_uri_component(value)
+    )
+}
+
+/// Delay before the first reconnect attempt, doubled after each failure
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Failed attempts after which a connection is reported as offline
+const OFFLINE_AFTER: u32 = 3;
+
+/// How long to wait before reconnect attempt number `attempts`. Jittered
+/// to spread out reconnects from many tabs after a server restart.
+fn backoff(attempts: u32) -> Duration {
+    MIN_BACKOFF
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+        .mul_f64(0.5 + js_sys::Math::random() / 2.0)
+}
+
+/// Whether the API at `auth_url` rejects the cookie event streams and
+/// WebSockets are opened with. Browsers don't say why those fail to
+/// connect, so `/auth`, which takes the same cookie, is asked instead.
+async fn rejects_login(auth_url: &str) -> bool {
+    let response = gloo_net::http::Request::get(auth_url).send().await;
+    matches!(response, Ok(response) if response.status() == 401)
+}
+
+fn is_online() -> bool {
+    leptos::window().navigator().on_line()
+}
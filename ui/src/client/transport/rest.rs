@@ -0,0 +1,193 @@
+use futures::{future::LocalBoxFuture, FutureExt};
+use gloo_net::http::{Method as HttpMethod, RequestBuilder};
+use leptos::Callback;
+
+use super::{sse, ApiRequest, Method, Transport};
+use crate::client::{ClientError, MediaEvents, Result};
+
+/// One HTTP request per API call, with events pushed over server-sent
+/// events
+pub struct RestTransport {
+    base: String,
+    token: Option<String>,
+}
+
+impl RestTransport {
+    pub fn new(base: &str, token: Option<String>) -> Self {
+        Self {
+            base: base.to_owned(),
+            token,
+        }
+    }
+}
+
+impl Transport for RestTransport {
+    fn send(&self, request: ApiRequest) -> LocalBoxFuture<'static, Result<String>> {
+        send(
+            format!("{}{}", self.base, request.path),
+            self.token.clone(),
+            request,
+        )
+        .boxed_local()
+    }
+
+    fn events(&self, on_unauthorized: Option<Callback<()>>) -> MediaEvents {
+        sse::connect(
+            format!("{}/events/media", self.base),
+            format!("{}/auth", self.base),
+            on_unauthorized,
+        )
+    }
+}
+
+impl From<gloo_net::Error> for ClientError {
+    fn from(e: gloo_net::Error) -> Self {
+        match e {
+            gloo_net::Error::SerdeError(e) => Self::Decode(e.to_string()),
+            e => Self::Network(e.to_string()),
+        }
+    }
+}
+
+async fn send(url: String, token: Option<String>, request: ApiRequest) -> Result<String> {
+    let method = match request.method {
+        Method::Get => HttpMethod::GET,
+        Method::Post => HttpMethod::POST,
+        Method::Patch => HttpMethod::PATCH,
+        Method::Delete => HttpMethod::DELETE,
+    };
+    let mut builder = RequestBuilder::new(&url)
+        .method(method)
+        .query(request.query)
+        .abort_signal(request.abort.as_ref());
+    if let Some(token) = token {
+        builder = builder.header("Authorization", &format!("Bearer {}", token));
+    }
+    let response = builder.send().await?;
+    // non-success responses carry the reason as plain text
+    let body = response.text().await?;
+    if response.ok() {
+        Ok(body)
+    } else {
+        Err(ClientError::Status(response.status(), body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use leptos::*;
+    use serde_json::json;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::{
+        client::{stand_in, transport::OFFLINE_AFTER, ConnectionState},
+        data::MediaEvent,
+    };
+
+    fn transport() -> RestTransport {
+        stand_in::reset();
+        RestTransport::new(
+            &format!("{}/api", stand_in::BASE),
+            Some("secret".to_string()),
+        )
+    }
+
+    #[wasm_bindgen_test]
+    async fn sends_requests_with_the_token() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/media", 200, "[]");
+        let request = ApiRequest::new(Method::Get, "/media".to_string()).query("q", "a b");
+        assert_eq!(transport.send(request).await, Ok("[]".to_string()));
+        let requests = stand_in::requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/api/media");
+        assert_eq!(requests[0].query, "q=a+b");
+        assert_eq!(
+            requests[0].headers.get("authorization").map(String::as_str),
+            Some("Bearer secret")
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn fails_on_error_statuses_and_network_errors() {
+        let transport = transport();
+        stand_in::respond("PATCH", "/api/media/1", 400, "unknown field: f");
+        let request = ApiRequest::new(Method::Patch, "/media/1".to_string()).query("f", "f");
+        assert_eq!(
+            transport.send(request.clone()).await,
+            Err(ClientError::Status(400, "unknown field: f".to_string()))
+        );
+        stand_in::set_up(false);
+        assert!(matches!(
+            transport.send(request).await,
+            Err(ClientError::Network(_))
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn streams_events_and_resumes_after_reconnecting() {
+        let transport = transport();
+        let (events, _owner) = as_child_of_current_owner(|()| transport.events(None))(());
+        let online = move || events.state.get_untracked() == ConnectionState::Online;
+        stand_in::until("the stream to open", online).await;
+        let url = format!("{}/api/events/media", stand_in::BASE);
+        assert_eq!(stand_in::connections(), vec![url.clone()]);
+
+        stand_in::push_event("e-1", json!({"type": "removed", "id": "1"}));
+        assert_eq!(
+            events.events.get_untracked(),
+            Some(MediaEvent::Removed {
+                id: "1".to_string()
+            })
+        );
+
+        stand_in::disconnect();
+        assert_eq!(events.state.get_untracked(), ConnectionState::Reconnecting);
+        stand_in::until("the stream to reconnect", online).await;
+        assert_eq!(
+            stand_in::connections(),
+            vec![url.clone(), format!("{}?last_event_id=e-1", url)]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn resyncs_after_reconnecting_without_an_event_id() {
+        let transport = transport();
+        let (events, _owner) = as_child_of_current_owner(|()| transport.events(None))(());
+        let online = move || events.state.get_untracked() == ConnectionState::Online;
+        stand_in::until("the stream to open", online).await;
+        stand_in::disconnect();
+        stand_in::until("the stream to reconnect", online).await;
+        assert_eq!(events.events.get_untracked(), Some(MediaEvent::Resync));
+    }
+
+    #[wasm_bindgen_test]
+    async fn stops_reconnecting_once_the_login_is_rejected() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/auth", 401, "login required");
+        stand_in::set_refused(true);
+        let unauthorized = create_rw_signal(false);
+        let (events, _owner) = as_child_of_current_owner(|()| {
+            transport.events(Some(Callback::new(move |()| unauthorized.set(true))))
+        })(());
+        // each attempt backs off longer than the last
+        for attempts in 2..=OFFLINE_AFTER as usize {
+            stand_in::until("another attempt to connect", || {
+                stand_in::connections().len() >= attempts
+            })
+            .await;
+        }
+        stand_in::until("the login to be rejected", move || {
+            unauthorized.get_untracked()
+        })
+        .await;
+        assert_eq!(stand_in::connections().len(), OFFLINE_AFTER as usize);
+        assert_eq!(events.state.get_untracked(), ConnectionState::Offline);
+        let paths = stand_in::requests()
+            .into_iter()
+            .map(|r| r.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/api/auth".to_string()]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{rc::Rc, time::Duration};
+
+use futures::{
+    future::{select, Either, LocalBoxFuture},
+    FutureExt,
+};
+use gloo_timers::future::TimeoutFuture;
+use leptos::Callback;
+
+use super::{backoff, ApiRequest, Transport};
+use crate::client::{ClientError, MediaEvents, Result};
+
+/// How long API requests may take and how often they are retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestPolicy {
+    /// Limit on each attempt, unless the request sets its own
+    pub timeout: Duration,
+    /// Attempts after the first one. Only requests that allow it are
+    /// retried.
+    pub retries: u32,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(15),
+            retries: 3,
+        }
+    }
+}
+
+/// A failed attempt that is about to be retried
+#[derive(Debug, Clone)]
+pub struct Retry {
+    /// e.g. `GET /media`
+    pub request: String,
+    /// Number of the attempt that will be made
+    pub attempt: u32,
+    pub attempts: u32,
+    pub delay: Duration,
+    pub error: ClientError,
+}
+
+/// Applies a [`RequestPolicy`] to the requests sent over another transport
+pub struct RetryingTransport {
+    inner: Rc<dyn Transport>,
+    policy: RequestPolicy,
+    on_retry: Option<Callback<Retry>>,
+}
+
+impl RetryingTransport {
+    pub fn new(
+        inner: Rc<dyn Transport>,
+        policy: RequestPolicy,
+        on_retry: Option<Callback<Retry>>,
+    ) -> Self {
+        Self {
+            inner,
+            policy,
+            on_retry,
+        }
+    }
+}
+
+impl Transport for RetryingTransport {
+    fn send(&self, request: ApiRequest) -> LocalBoxFuture<'static, Result<String>> {
+        send(Rc::clone(&self.inner), self.policy, self.on_retry, request).boxed_local()
+    }
+
+    fn events(&self, on_unauthorized: Option<Callback<()>>) -> MediaEvents {
+        self.inner.events(on_unauthorized)
+    }
+}
+
+/// Failures that may go away by themselves
+fn is_transient(error: &ClientError) -> bool {
+    matches!(
+        error,
+        ClientError::Network(_) | ClientError::Timeout(_) | ClientError::Status(502..=504, _)
+    )
+}
+
+async fn send(
+    inner: Rc<dyn Transport>,
+    policy: RequestPolicy,
+    on_retry: Option<Callback<Retry>>,
+    request: ApiRequest,
+) -> Result<String> {
+    let timeout = request.timeout.unwrap_or(policy.timeout);
+    let retries = if request.retry { policy.retries } else { 0 };
+    let mut attempt = 1;
+    loop {
+        // a request that timed out is cancelled rather than left to finish
+        // in the background
+        let controller = web_sys::AbortController::new().ok();
+        let response = inner.send(match &controller {
+            Some(controller) => request.clone().abort(controller.signal()),
+            None => request.clone(),
+        });
+        let limit = TimeoutFuture::new(timeout.as_millis().min(u32::MAX as u128) as u32);
+        let result = match select(response, limit).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => {
+                if let Some(controller) = controller {
+                    controller.abort();
+                }
+                Err(ClientError::Timeout(timeout))
+            }
+        };
+        match result {
+            Err(error) if attempt <= retries && is_transient(&error) => {
+                let delay = backoff(attempt);
+                attempt += 1;
+                if let Some(on_retry) = on_retry {
+                    on_retry(Retry {
+                        request: format!("{} {}", request.method.as_str(), request.path),
+                        attempt,
+                        attempts: retries + 1,
+                        delay,
+                        error,
+                    })
+                }
+                TimeoutFuture::new(delay.as_millis() as u32).await
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::client::{
+        stand_in,
+        transport::{Method, RestTransport},
+    };
+
+    fn transport(retries: u32) -> RetryingTransport {
+        stand_in::reset();
+        let inner = RestTransport::new(&format!("{}/api", stand_in::BASE), None);
+        let policy = RequestPolicy {
+            timeout: Duration::from_secs(1),
+            retries,
+        };
+        RetryingTransport::new(Rc::new(inner), policy, None)
+    }
+
+    #[wasm_bindgen_test]
+    async fn retries_idempotent_requests() {
+        let transport = transport(2);
+        stand_in::respond("GET", "/api/media", 503, "");
+        let request = ApiRequest::new(Method::Get, "/media".to_string());
+        assert_eq!(
+            transport.send(request).await,
+            Err(ClientError::Status(503, String::new()))
+        );
+        assert_eq!(stand_in::requests().len(), 3);
+    }
+
+    #[wasm_bindgen_test]
+    async fn sends_other_requests_once() {
+        let transport = transport(2);
+        stand_in::respond("PATCH", "/api/media/1", 503, "");
+        stand_in::respond("DELETE", "/api/media/1", 503, "");
+        let patch = ApiRequest::new(Method::Patch, "/media/1".to_string());
+        assert!(transport.send(patch).await.is_err());
+        let delete = ApiRequest::new(Method::Delete, "/media/1".to_string()).no_retry();
+        assert!(transport.send(delete).await.is_err());
+        assert_eq!(stand_in::requests().len(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    async fn times_out() {
+        let transport = transport(0);
+        stand_in::respond_with("GET", "/api/media", 200, "[]", &[], Duration::from_secs(5));
+        let request = ApiRequest::new(Method::Get, "/media".to_string());
+        assert_eq!(
+            transport.send(request).await,
+            Err(ClientError::Timeout(Duration::from_secs(1)))
+        );
+        assert!(stand_in::requests()[0].aborted);
+    }
+}
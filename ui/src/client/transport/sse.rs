@@ -0,0 +1,233 @@
+//! Server-sent media events that survive dropped connections.
+//!
+//! `EventSource` gives up for good on some errors and browsers don't
+//! notice when a connection silently dies during sleep, so reconnects are
+//! handled here with exponential backoff. Reconnects resume from the last
+//! event id seen; the server replies with a `resync` event if it no
+//! longer knows that id. Reconnects stop once the failures turn out to be
+//! down to a rejected login.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use leptos::{leptos_dom::helpers::TimeoutHandle, wasm_bindgen::prelude::*, *};
+
+use super::{backoff, is_online, rejects_login, with_param, OFFLINE_AFTER};
+use crate::{
+    client::{ConnectionState, MediaEvents},
+    data::MediaEvent,
+    log,
+};
+
+struct Handlers {
+    _open: Closure<dyn FnMut(web_sys::Event)>,
+    _message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _error: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+struct Connection {
+    url: String,
+    /// Where to ask whether the login is still good
+    auth_url: String,
+    on_unauthorized: Option<Callback<()>>,
+    source: RefCell<Option<web_sys::EventSource>>,
+    handlers: RefCell<Option<Handlers>>,
+    timer: Cell<Option<TimeoutHandle>>,
+    last_event_id: RefCell<Option<String>>,
+    /// Failed attempts since the connection was last open
+    attempts: Cell<u32>,
+    /// Whether the connection was ever open, i.e. events may have been missed
+    opened: Cell<bool>,
+    closed: Cell<bool>,
+    set_event: WriteSignal<Option<MediaEvent>>,
+    set_state: WriteSignal<ConnectionState>,
+}
+
+impl Connection {
+    fn open(self: &Rc<Self>) {
+        if let Some(timer) = self.timer.take() {
+            timer.clear();
+        }
+        if let Some(source) = self.source.take() {
+            source.close();
+        }
+        if self.closed.get() {
+            return;
+        }
+        let url = match &*self.last_event_id.borrow() {
+            Some(id) => with_param(&self.url, "last_event_id", id),
+            None => self.url.clone(),
+        };
+        let source = match web_sys::EventSource::new(&url) {
+            Ok(source) => source,
+            Err(e) => {
+                log!("failed to open event source: {:?}", e);
+                return self.retry();
+            }
+        };
+
+        let this = Rc::downgrade(self);
+        let on_open = Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+            if let Some(this) = this.upgrade() {
+                this.on_open()
+            }
+        });
+        let this = Rc::downgrade(self);
+        let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |e| {
+            if let Some(this) = this.upgrade() {
+                this.on_message(e)
+            }
+        });
+        let this = Rc::downgrade(self);
+        let on_error = Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+            if let Some(this) = this.upgrade() {
+                this.retry()
+            }
+        });
+        source.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        source.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        *self.source.borrow_mut() = Some(source);
+        *self.handlers.borrow_mut() = Some(Handlers {
+            _open: on_open,
+            _message: on_message,
+            _error: on_error,
+        });
+    }
+
+    fn on_open(&self) {
+        let reconnected = self.opened.replace(true);
+        self.attempts.set(0);
+        self.set_state.set(ConnectionState::Online);
+        // without an id to resume from there is no telling what was missed
+        if reconnected && self.last_event_id.borrow().is_none() {
+            self.set_event.set(Some(MediaEvent::Resync));
+        }
+    }
+
+    fn on_message(&self, e: web_sys::MessageEvent) {
+        let id = e.last_event_id();
+        if !id.is_empty() {
+            *self.last_event_id.borrow_mut() = Some(id);
+        }
+        let Some(data) = e.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<MediaEvent>(&data) {
+            Ok(event) => self.set_event.set(Some(event)),
+            Err(e) => log!("ignoring malformed event {:?}: {}", data, e),
+        }
+    }
+
+    /// Drop the current connection and try again after a backoff
+    fn retry(self: &Rc<Self>) {
+        if let Some(source) = self.source.take() {
+            source.close();
+        }
+        if self.closed.get() {
+            return;
+        }
+        let attempts = self.attempts.get() + 1;
+        self.attempts.set(attempts);
+        self.set_state.set(if attempts > OFFLINE_AFTER || !is_online() {
+            ConnectionState::Offline
+        } else {
+            ConnectionState::Reconnecting
+        });
+        if attempts == OFFLINE_AFTER && is_online() {
+            self.check_login();
+        }
+        let this = Rc::downgrade(self);
+        let timer = set_timeout_with_handle(
+            move || {
+                if let Some(this) = this.upgrade() {
+                    this.open()
+                }
+            },
+            backoff(attempts),
+        );
+        self.timer.set(timer.ok());
+    }
+
+    /// Give up for good if the server rejects the login, since no amount
+    /// of reconnecting gets past that
+    fn check_login(self: &Rc<Self>) {
+        let Some(on_unauthorized) = self.on_unauthorized else {
+            return;
+        };
+        let (this, auth_url) = (Rc::downgrade(self), self.auth_url.clone());
+        spawn_local(async move {
+            if !rejects_login(&auth_url).await {
+                return;
+            }
+            if let Some(this) = this.upgrade().filter(|this| !this.closed.get()) {
+                this.close();
+                this.set_state.set(ConnectionState::Offline);
+                on_unauthorized(());
+            }
+        });
+    }
+
+    fn close(&self) {
+        self.closed.set(true);
+        if let Some(timer) = self.timer.take() {
+            timer.clear();
+        }
+        if let Some(source) = self.source.take() {
+            source.close();
+        }
+        self.handlers.take();
+    }
+}
+
+/// Subscribe to the events published at `url`. The connection is closed
+/// when the current reactive owner is disposed, or once `auth_url` rejects
+/// the login after repeated failures, which is reported to
+/// `on_unauthorized`.
+pub fn connect(
+    url: String,
+    auth_url: String,
+    on_unauthorized: Option<Callback<()>>,
+) -> MediaEvents {
+    let (event, set_event) = create_signal(None::<MediaEvent>);
+    let (state, set_state) = create_signal(ConnectionState::Reconnecting);
+    let connection = Rc::new(Connection {
+        url,
+        auth_url,
+        on_unauthorized,
+        source: RefCell::new(None),
+        handlers: RefCell::new(None),
+        timer: Cell::new(None),
+        last_event_id: RefCell::new(None),
+        attempts: Cell::new(0),
+        opened: Cell::new(false),
+        closed: Cell::new(false),
+        set_event,
+        set_state,
+    });
+    connection.open();
+
+    // don't wait out the backoff once the network is back
+    let _ = leptos_use::use_event_listener(window(), ev::online, {
+        let connection = Rc::downgrade(&connection);
+        move |_| {
+            if let Some(connection) = connection.upgrade() {
+                if state.get_untracked() != ConnectionState::Online {
+                    connection.attempts.set(0);
+                    connection.open();
+                }
+            }
+        }
+    });
+    let _ = leptos_use::use_event_listener(window(), ev::offline, move |_| {
+        set_state.set(ConnectionState::Offline)
+    });
+    on_cleanup(move || connection.close());
+
+    MediaEvents {
+        events: event.into(),
+        state: state.into(),
+    }
+}
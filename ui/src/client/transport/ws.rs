@@ -0,0 +1,553 @@
+//! Requests, responses and media events multiplexed over one WebSocket.
+//!
+//! Every request carries an id that the server echoes in its response.
+//! Requests made while the socket is down are queued until it reconnects,
+//! unless their caller stops waiting first; requests in flight when it
+//! drops fail with a network error since there is no telling whether the
+//! server saw them. Reconnects stop once the failures turn out to be down
+//! to a rejected login, failing queued requests with a 401.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use futures::{channel::oneshot, future::LocalBoxFuture, FutureExt};
+use leptos::{leptos_dom::helpers::TimeoutHandle, wasm_bindgen::prelude::*, *};
+use serde::{Deserialize, Serialize};
+
+use super::{backoff, is_online, rejects_login, with_param, ApiRequest, Transport, OFFLINE_AFTER};
+use crate::{
+    client::{ClientError, ConnectionState, MediaEvents, Result},
+    data::MediaEvent,
+    log,
+};
+
+#[derive(Serialize)]
+struct WireRequest<'a> {
+    id: &'a str,
+    method: &'static str,
+    path: &'a str,
+    query: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum WireMessage {
+    Response {
+        id: String,
+        status: u16,
+        #[serde(default)]
+        body: String,
+    },
+    Event {
+        #[serde(default)]
+        id: Option<String>,
+        data: Box<MediaEvent>,
+    },
+}
+
+struct Handlers {
+    _open: Closure<dyn FnMut(web_sys::Event)>,
+    _message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _close: Closure<dyn FnMut(web_sys::CloseEvent)>,
+}
+
+/// Signals of the component tree listening for events
+struct Subscriber {
+    set_event: WriteSignal<Option<MediaEvent>>,
+    set_state: WriteSignal<ConnectionState>,
+    on_unauthorized: Option<Callback<()>>,
+}
+
+struct Socket {
+    url: String,
+    /// Where to ask whether the login is still good
+    auth_url: String,
+    ws: RefCell<Option<web_sys::WebSocket>>,
+    handlers: RefCell<Option<Handlers>>,
+    timer: Cell<Option<TimeoutHandle>>,
+    /// Requests waiting for the socket to open, with their ids
+    queue: RefCell<Vec<(String, String)>>,
+    /// Requests waiting for a response, by request id
+    pending: RefCell<HashMap<String, oneshot::Sender<Result<String>>>>,
+    last_event_id: RefCell<Option<String>>,
+    /// Failed attempts since the socket was last open
+    attempts: Cell<u32>,
+    /// Whether the socket was ever open, i.e. events may have been missed
+    opened: Cell<bool>,
+    state: Cell<ConnectionState>,
+    subscriber: RefCell<Option<Subscriber>>,
+}
+
+impl Socket {
+    fn open(self: &Rc<Self>) {
+        if let Some(timer) = self.timer.take() {
+            timer.clear();
+        }
+        self.disconnect();
+        let url = match &*self.last_event_id.borrow() {
+            Some(id) => with_param(&self.url, "last_event_id", id),
+            None => self.url.clone(),
+        };
+        let ws = match web_sys::WebSocket::new(&url) {
+            Ok(ws) => ws,
+            Err(e) => {
+                log!("failed to open websocket: {:?}", e);
+                return self.retry();
+            }
+        };
+
+        let this = Rc::downgrade(self);
+        let on_open = Closure::<dyn FnMut(web_sys::Event)>::new(move |_| {
+            if let Some(this) = this.upgrade() {
+                this.on_open()
+            }
+        });
+        let this = Rc::downgrade(self);
+        let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |e| {
+            if let Some(this) = this.upgrade() {
+                this.on_message(e)
+            }
+        });
+        // errors are always followed by a close
+        let this = Rc::downgrade(self);
+        let on_close = Closure::<dyn FnMut(web_sys::CloseEvent)>::new(move |_| {
+            if let Some(this) = this.upgrade() {
+                this.retry()
+            }
+        });
+        ws.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        *self.ws.borrow_mut() = Some(ws);
+        *self.handlers.borrow_mut() = Some(Handlers {
+            _open: on_open,
+            _message: on_message,
+            _close: on_close,
+        });
+    }
+
+    /// Close the socket without triggering a reconnect
+    fn disconnect(&self) {
+        if let Some(ws) = self.ws.take() {
+            ws.set_onopen(None);
+            ws.set_onmessage(None);
+            ws.set_onclose(None);
+            let _ = ws.close();
+        }
+        self.handlers.take();
+    }
+
+    fn on_open(&self) {
+        let reconnected = self.opened.replace(true);
+        self.attempts.set(0);
+        self.set_state(ConnectionState::Online);
+        if let Some(ws) = &*self.ws.borrow() {
+            for (_, message) in self.queue.take() {
+                if let Err(e) = ws.send_with_str(&message) {
+                    log!("failed to send queued request: {:?}", e);
+                }
+            }
+        }
+        // without an id to resume from there is no telling what was missed
+        if reconnected && self.last_event_id.borrow().is_none() {
+            self.emit(MediaEvent::Resync);
+        }
+    }
+
+    fn on_message(&self, e: web_sys::MessageEvent) {
+        let Some(data) = e.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<WireMessage>(&data) {
+            Ok(WireMessage::Response { id, status, body }) => {
+                let response = self.pending.borrow_mut().remove(&id);
+                if let Some(response) = response {
+                    let _ = response.send(if (200..300).contains(&status) {
+                        Ok(body)
+                    } else {
+                        Err(ClientError::Status(status, body))
+                    });
+                }
+            }
+            Ok(WireMessage::Event { id, data }) => {
+                if id.is_some() {
+                    *self.last_event_id.borrow_mut() = id;
+                }
+                self.emit(*data)
+            }
+            Err(e) => log!("ignoring malformed message {:?}: {}", data, e),
+        }
+    }
+
+    /// Drop the current socket and try again after a backoff
+    fn retry(self: &Rc<Self>) {
+        self.disconnect();
+        // queued requests never left and are sent after reconnecting
+        let queued = self
+            .queue
+            .borrow()
+            .iter()
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        self.pending
+            .borrow_mut()
+            .retain(|id, _| queued.contains(id));
+        let attempts = self.attempts.get() + 1;
+        self.attempts.set(attempts);
+        self.set_state(if attempts > OFFLINE_AFTER || !is_online() {
+            ConnectionState::Offline
+        } else {
+            ConnectionState::Reconnecting
+        });
+        if attempts == OFFLINE_AFTER && is_online() {
+            self.check_login();
+        }
+        let this = Rc::downgrade(self);
+        let timer = set_timeout_with_handle(
+            move || {
+                if let Some(this) = this.upgrade() {
+                    this.open()
+                }
+            },
+            backoff(attempts),
+        );
+        self.timer.set(timer.ok());
+    }
+
+    /// Stop reconnecting if the server rejects the login, since no amount
+    /// of reconnecting gets past that. The next request tries again.
+    fn check_login(self: &Rc<Self>) {
+        let (this, auth_url) = (Rc::downgrade(self), self.auth_url.clone());
+        spawn_local(async move {
+            if !rejects_login(&auth_url).await {
+                return;
+            }
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+            // reconnected in the meantime
+            if this.state.get() == ConnectionState::Online {
+                return;
+            }
+            if let Some(timer) = this.timer.take() {
+                timer.clear();
+            }
+            this.disconnect();
+            this.queue.take();
+            for (_, response) in this.pending.take() {
+                let _ = response.send(Err(ClientError::Status(401, "login required".to_string())));
+            }
+            this.set_state(ConnectionState::Offline);
+            let on_unauthorized = this
+                .subscriber
+                .borrow()
+                .as_ref()
+                .and_then(|s| s.on_unauthorized);
+            if let Some(on_unauthorized) = on_unauthorized {
+                on_unauthorized(())
+            }
+        });
+    }
+
+    /// Send request `id` now if the socket is open, otherwise once it is
+    fn send(self: &Rc<Self>, id: String, message: String) {
+        let sent = match &*self.ws.borrow() {
+            Some(ws) if ws.ready_state() == web_sys::WebSocket::OPEN => {
+                ws.send_with_str(&message).is_ok()
+            }
+            _ => false,
+        };
+        if !sent {
+            self.queue.borrow_mut().push((id, message));
+            self.connect();
+        }
+    }
+
+    /// Open the socket unless it is open or a reconnect is scheduled
+    fn connect(self: &Rc<Self>) {
+        if self.ws.borrow().is_none() && self.timer.get().is_none() {
+            self.open()
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        self.state.set(state);
+        let set_state = self.subscriber.borrow().as_ref().map(|s| s.set_state);
+        if let Some(set_state) = set_state {
+            set_state.set(state)
+        }
+    }
+
+    fn emit(&self, event: MediaEvent) {
+        // the signal is copied out so that effects may resubscribe
+        let set_event = self.subscriber.borrow().as_ref().map(|s| s.set_event);
+        if let Some(set_event) = set_event {
+            set_event.set(Some(event))
+        }
+    }
+}
+
+/// Withdraws a request when its caller stops waiting for the response,
+/// e.g. after a timeout, so that a queued request isn't sent after all
+struct Withdraw {
+    socket: Rc<Socket>,
+    id: String,
+}
+
+impl Drop for Withdraw {
+    fn drop(&mut self) {
+        self.socket
+            .queue
+            .borrow_mut()
+            .retain(|(id, _)| *id != self.id);
+        self.socket.pending.borrow_mut().remove(&self.id);
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.clear();
+        }
+        self.disconnect();
+    }
+}
+
+/// Every request and event travels over a single WebSocket at `/ws`
+pub struct WsTransport {
+    socket: Rc<Socket>,
+}
+
+impl WsTransport {
+    /// `base` is the HTTP(S) root of the API. The socket is opened with
+    /// the cookie logging in sets, since browsers can't set its headers.
+    pub fn new(base: &str) -> Self {
+        Self {
+            socket: Rc::new(Socket {
+                url: format!("{}/ws", base.replacen("http", "ws", 1)),
+                auth_url: format!("{}/auth", base),
+                ws: RefCell::new(None),
+                handlers: RefCell::new(None),
+                timer: Cell::new(None),
+                queue: RefCell::new(Vec::new()),
+                pending: RefCell::new(HashMap::new()),
+                last_event_id: RefCell::new(None),
+                attempts: Cell::new(0),
+                opened: Cell::new(false),
+                state: Cell::new(ConnectionState::Reconnecting),
+                subscriber: RefCell::new(None),
+            }),
+        }
+    }
+}
+
+impl Transport for WsTransport {
+    fn send(&self, request: ApiRequest) -> LocalBoxFuture<'static, Result<String>> {
+        let socket = Rc::clone(&self.socket);
+        async move {
+            let id = uuid::Uuid::new_v4().to_string();
+            let message = serde_json::to_string(&WireRequest {
+                id: &id,
+                method: request.method.as_str(),
+                path: &request.path,
+                query: request.query_string(),
+            })
+            .map_err(|e| ClientError::Invalid(e.to_string()))?;
+            let (response, receiver) = oneshot::channel();
+            socket.pending.borrow_mut().insert(id.clone(), response);
+            socket.send(id.clone(), message);
+            let _withdraw = Withdraw { socket, id };
+            receiver.await.unwrap_or_else(|_| {
+                Err(ClientError::Network(
+                    "connection closed before the server responded".to_string(),
+                ))
+            })
+        }
+        .boxed_local()
+    }
+
+    fn events(&self, on_unauthorized: Option<Callback<()>>) -> MediaEvents {
+        let (event, set_event) = create_signal(None::<MediaEvent>);
+        let (state, set_state) = create_signal(self.socket.state.get());
+        *self.socket.subscriber.borrow_mut() = Some(Subscriber {
+            set_event,
+            set_state,
+            on_unauthorized,
+        });
+        self.socket.connect();
+
+        // don't wait out the backoff once the network is back
+        let socket = Rc::downgrade(&self.socket);
+        let _ = leptos_use::use_event_listener(window(), ev::online, move |_| {
+            if let Some(socket) = socket.upgrade() {
+                if socket.state.get() != ConnectionState::Online {
+                    socket.attempts.set(0);
+                    socket.open();
+                }
+            }
+        });
+        let socket = Rc::downgrade(&self.socket);
+        on_cleanup(move || {
+            if let Some(socket) = socket.upgrade() {
+                socket.subscriber.take();
+            }
+        });
+
+        MediaEvents {
+            events: event.into(),
+            state: state.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{
+        future::{select, Either},
+        join,
+    };
+    use gloo_timers::future::TimeoutFuture;
+    use serde_json::json;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::client::{stand_in, transport::Method};
+
+    fn transport() -> WsTransport {
+        stand_in::reset();
+        WsTransport::new(&format!("{}/api", stand_in::BASE))
+    }
+
+    fn get_media() -> ApiRequest {
+        ApiRequest::new(Method::Get, "/media".to_string()).query("q", "a b")
+    }
+
+    fn set_title() -> ApiRequest {
+        ApiRequest::new(Method::Patch, "/media/1".to_string())
+            .query("f", "title")
+            .query("v", "New")
+    }
+
+    #[wasm_bindgen_test]
+    async fn carries_requests_and_responses() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/media", 200, "[]");
+        stand_in::respond("PATCH", "/api/media/1", 400, "unknown field: f");
+        assert_eq!(transport.send(get_media()).await, Ok("[]".to_string()));
+        assert_eq!(
+            transport.send(set_title()).await,
+            Err(ClientError::Status(400, "unknown field: f".to_string()))
+        );
+        assert_eq!(
+            stand_in::connections(),
+            vec!["ws://stand-in.test/api/ws".to_string()]
+        );
+        let requests = stand_in::requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/api/media");
+        assert_eq!(requests[0].query, "q=a%20b");
+    }
+
+    #[wasm_bindgen_test]
+    async fn queues_requests_until_the_server_is_back() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/media", 200, "[]");
+        stand_in::set_up(false);
+        let (response, ()) = join!(transport.send(get_media()), async {
+            stand_in::until("the first attempt to connect to fail", || {
+                transport.socket.attempts.get() > 0
+            })
+            .await;
+            stand_in::set_up(true);
+        });
+        assert_eq!(response, Ok("[]".to_string()));
+        assert_eq!(stand_in::requests().len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    async fn withdraws_queued_requests_whose_caller_gave_up() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/media", 200, "[]");
+        stand_in::respond("PATCH", "/api/media/1", 200, "");
+        stand_in::set_up(false);
+        // the way a request times out
+        let gave_up = select(transport.send(set_title()), TimeoutFuture::new(50)).await;
+        assert!(matches!(gave_up, Either::Right(_)));
+        drop(gave_up);
+        assert!(transport.socket.queue.borrow().is_empty());
+        assert!(transport.socket.pending.borrow().is_empty());
+
+        stand_in::set_up(true);
+        assert_eq!(transport.send(get_media()).await, Ok("[]".to_string()));
+        let methods = stand_in::requests()
+            .into_iter()
+            .map(|r| r.method)
+            .collect::<Vec<_>>();
+        assert_eq!(methods, vec!["GET".to_string()]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn fails_requests_in_flight_when_the_socket_drops() {
+        let transport = transport();
+        stand_in::respond_with("GET", "/api/media", 200, "[]", &[], Duration::from_secs(60));
+        let (response, ()) = join!(transport.send(get_media()), async {
+            stand_in::until("the request to reach the server", || {
+                !stand_in::requests().is_empty()
+            })
+            .await;
+            stand_in::disconnect();
+        });
+        assert!(matches!(response, Err(ClientError::Network(_))));
+    }
+
+    #[wasm_bindgen_test]
+    async fn delivers_events_and_resumes_after_reconnecting() {
+        let transport = transport();
+        let (events, _owner) = as_child_of_current_owner(|()| transport.events(None))(());
+        let online = move || events.state.get_untracked() == ConnectionState::Online;
+        stand_in::until("the socket to open", online).await;
+
+        stand_in::push_event("e-1", json!({"type": "removed", "id": "1"}));
+        assert_eq!(
+            events.events.get_untracked(),
+            Some(MediaEvent::Removed {
+                id: "1".to_string()
+            })
+        );
+
+        stand_in::disconnect();
+        assert_eq!(events.state.get_untracked(), ConnectionState::Reconnecting);
+        stand_in::until("the socket to reconnect", online).await;
+        let url = "ws://stand-in.test/api/ws";
+        assert_eq!(
+            stand_in::connections(),
+            vec![url.to_string(), format!("{}?last_event_id=e-1", url)]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn stops_reconnecting_once_the_login_is_rejected() {
+        let transport = transport();
+        stand_in::respond("GET", "/api/auth", 401, "login required");
+        stand_in::set_refused(true);
+        let unauthorized = create_rw_signal(false);
+        let (events, _owner) = as_child_of_current_owner(|()| {
+            transport.events(Some(Callback::new(move |()| unauthorized.set(true))))
+        })(());
+        // queued until the socket opens, which it never does
+        let response = transport.send(get_media()).await;
+        assert_eq!(
+            response,
+            Err(ClientError::Status(401, "login required".to_string()))
+        );
+        assert!(unauthorized.get_untracked());
+        assert_eq!(stand_in::connections().len(), OFFLINE_AFTER as usize);
+        assert_eq!(events.state.get_untracked(), ConnectionState::Offline);
+        assert!(transport.socket.timer.get().is_none());
+    }
+}
@@ -0,0 +1,190 @@
+//! Resumable uploads with the tus 1.0 protocol: create the upload, then
+//! send the file a chunk at a time.
+
+use std::cell::Cell;
+
+use base64::Engine;
+use gloo_net::http::{Method, Request, RequestBuilder, Response};
+use gloo_timers::future::TimeoutFuture;
+use leptos::*;
+
+use super::{ClientError, Result, UploadState, UploadTask};
+
+const TUS_VERSION: &str = "1.0.0";
+
+/// Smallest chunk sent under a bandwidth cap, so that requests aren't
+/// mostly overhead
+const MIN_THROTTLED_CHUNK: f64 = 256_000.0;
+
+thread_local! {
+    /// When the uplink is free again under the bandwidth cap, as a
+    /// `Date.now()` timestamp. Shared by every upload on the page.
+    static UPLINK_FREE_AT: Cell<f64> = const { Cell::new(0.0) };
+}
+
+/// How often a paused upload checks whether it was resumed
+const PAUSE_POLL_MS: u32 = 250;
+
+/// Upload `file` to the tus endpoint at `url`. Picks up the upload at
+/// `task.location` if the server still has it, and follows changes to
+/// `task.policy` from one chunk to the next.
+pub async fn upload(
+    url: &str,
+    token: Option<&str>,
+    file: &web_sys::File,
+    metadata: &[(&str, String)],
+    task: UploadTask,
+) -> Result<()> {
+    let resumed = match task.location.get_untracked() {
+        Some(location) => match offset(&location, token).await {
+            Ok(offset) => Some((location, offset)),
+            // expired or never finished creating
+            Err(ClientError::Status(404 | 410, _)) => None,
+            Err(e) => return Err(e),
+        },
+        None => None,
+    };
+    let (location, mut offset) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            let location = create(url, token, file.size(), metadata).await?;
+            task.location.set(Some(location.clone()));
+            (location, 0.0)
+        }
+    };
+    task.sent.set(offset);
+    while offset < file.size() {
+        match task.state.get_untracked() {
+            UploadState::Paused => {
+                TimeoutFuture::new(PAUSE_POLL_MS).await;
+                continue;
+            }
+            UploadState::Cancelled => return terminate(&location, token).await,
+            _ => {}
+        }
+        let policy = task.policy.get_untracked();
+        // under a cap, chunks of about two seconds' worth keep the uplink
+        // from being flooded in bursts
+        let chunk_size = match policy.bandwidth {
+            Some(bandwidth) => policy
+                .chunk_size
+                .min(2.0 * bandwidth)
+                .max(MIN_THROTTLED_CHUNK),
+            None => policy.chunk_size,
+        };
+        let end = (offset + chunk_size).min(file.size());
+        let chunk = file
+            .slice_with_f64_and_f64(offset, end)
+            .map_err(|e| ClientError::Tus(format!("{:?}", e)))?;
+        // the wait counts towards the rate, so the ETA allows for the cap
+        let started = js_sys::Date::now();
+        if let Some(bandwidth) = policy.bandwidth {
+            throttle(end - offset, bandwidth).await
+        }
+        let response = authorized(Request::patch(&location), token)
+            .header("Upload-Offset", &offset.to_string())
+            .header("Content-Type", "application/offset+octet-stream")
+            .body(chunk)?
+            .send()
+            .await?;
+        expect(&response, 204).await?;
+        let sent = upload_offset(&response)?.unwrap_or(end);
+        task.progress(
+            sent,
+            sent - offset,
+            (js_sys::Date::now() - started) / 1000.0,
+        );
+        offset = sent;
+    }
+    Ok(())
+}
+
+/// Wait for a turn to send `bytes` without all uploads together going
+/// over `bandwidth` bytes per second
+async fn throttle(bytes: f64, bandwidth: f64) {
+    let now = js_sys::Date::now();
+    let start = UPLINK_FREE_AT.get().max(now);
+    UPLINK_FREE_AT.set(start + 1000.0 * bytes / bandwidth);
+    if start > now {
+        TimeoutFuture::new((start - now) as u32).await
+    }
+}
+
+/// Bytes of the upload at `location` the server has
+async fn offset(location: &str, token: Option<&str>) -> Result<f64> {
+    let response = authorized(RequestBuilder::new(location).method(Method::HEAD), token)
+        .send()
+        .await?;
+    expect(&response, 200).await?;
+    upload_offset(&response)?
+        .ok_or_else(|| ClientError::Tus("server didn't say how much it has".to_string()))
+}
+
+/// Have the server throw away a cancelled upload
+async fn terminate(location: &str, token: Option<&str>) -> Result<()> {
+    let response = authorized(Request::delete(location), token).send().await?;
+    expect(&response, 204).await
+}
+
+fn upload_offset(response: &Response) -> Result<Option<f64>> {
+    response
+        .headers()
+        .get("Upload-Offset")
+        .map(|o| {
+            o.parse()
+                .map_err(|_| ClientError::Tus(format!("invalid Upload-Offset: {}", o)))
+        })
+        .transpose()
+}
+
+/// Register an upload of `size` bytes and return its URL
+async fn create(
+    url: &str,
+    token: Option<&str>,
+    size: f64,
+    metadata: &[(&str, String)],
+) -> Result<String> {
+    let mut request = authorized(Request::post(url), token)
+        .header("Upload-Length", &size.to_string())
+        .header("Content-Length", "0");
+    if !metadata.is_empty() {
+        let metadata = metadata
+            .iter()
+            .map(|(key, value)| {
+                let value = base64::prelude::BASE64_STANDARD.encode(value);
+                format!("{} {}", key, value)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        request = request.header("Upload-Metadata", &metadata);
+    }
+    let response = request.send().await?;
+    expect(&response, 201).await?;
+    let location = response
+        .headers()
+        .get("Location")
+        .ok_or_else(|| ClientError::Tus("server didn't say where the upload is".to_string()))?;
+    // the location may be relative to the endpoint
+    web_sys::Url::new_with_base(&location, url)
+        .map(|u| u.href())
+        .map_err(|_| ClientError::Tus(format!("invalid upload location: {}", location)))
+}
+
+fn authorized(request: RequestBuilder, token: Option<&str>) -> RequestBuilder {
+    let request = request.header("Tus-Resumable", TUS_VERSION);
+    match token {
+        Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+async fn expect(response: &Response, status: u16) -> Result<()> {
+    if response.status() == status {
+        Ok(())
+    } else {
+        Err(ClientError::Status(
+            response.status(),
+            response.text().await.unwrap_or_default(),
+        ))
+    }
+}
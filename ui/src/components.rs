@@ -1,8 +1,14 @@
+pub mod auth;
 pub mod dashboard;
 pub mod notification_tray;
+pub mod remotes;
+pub mod selection;
+pub mod uploads;
 
 use leptos::*;
 
+use crate::client::ClientError;
+
 #[component]
 pub fn LoremIpsum() -> impl IntoView {
     view! {
@@ -63,6 +69,56 @@ where
     }
 }
 
+/// Display a failed request along with a button to try it again
+#[component]
+pub fn ErrorMessage<F>(error: ClientError, retry: F) -> impl IntoView
+where
+    F: Fn() + 'static,
+{
+    view! {
+        <div class="error-message">
+            <span>{error.to_string()}</span>
+            <button on:click=move |_| retry()>"Retry"</button>
+        </div>
+    }
+}
+
+/// Modal asking the user to confirm a destructive operation
+#[component]
+pub fn ConfirmDialog<F>(
+    show: RwSignal<bool>,
+    message: String,
+    #[prop(default = "Delete".to_string())] confirm: String,
+    on_confirm: F,
+) -> impl IntoView
+where
+    F: Fn() + 'static,
+{
+    let on_confirm = store_value(on_confirm);
+    view! {
+        <Show when=show>
+            <div class="dialog-backdrop" on:click=move |_| show.set(false)>
+                <div class="dialog" role="alertdialog" on:click=|e| e.stop_propagation()>
+                    <p>{message.clone()}</p>
+                    <div class="dialog-buttons">
+                        <button on:click=move |_| show.set(false)>"Cancel"</button>
+                        <button
+                            class="danger"
+                            on:click=move |_| {
+                                show.set(false);
+                                on_confirm.with_value(|f| f());
+                            }
+                        >
+
+                            {confirm.clone()}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        </Show>
+    }
+}
+
 #[cfg(web_sys_unstable_apis)]
 #[component]
 pub fn CopyButton(value: String) -> impl IntoView {
@@ -0,0 +1,73 @@
+use leptos::*;
+use leptos_router::{use_location, use_navigate, NavigateOptions};
+
+use crate::{
+    auth::{login_path, Auth},
+    path,
+};
+
+/// Go to the login page whenever a remote asks for it. Must be rendered
+/// inside the router.
+#[component]
+pub fn LoginRedirect() -> impl IntoView {
+    let auth = use_context::<Auth>().unwrap();
+    let location = use_location();
+    let navigate = use_navigate();
+    create_effect(move |_| {
+        let Some(remote) = auth.required() else {
+            return;
+        };
+        let (pathname, search) = (
+            location.pathname.get_untracked(),
+            location.search.get_untracked(),
+        );
+        if pathname == path("login") {
+            return;
+        }
+        let next = match search.as_str() {
+            "" => pathname,
+            search => format!("{}?{}", pathname, search.trim_start_matches('?')),
+        };
+        navigate(&login_path(&remote, &next), NavigateOptions::default())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use leptos_router::Router;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::client::stand_in;
+
+    #[wasm_bindgen_test]
+    async fn comes_back_to_the_same_route_after_logging_in() {
+        let history = window().history().unwrap();
+        let location = window().location();
+        let started_at = location.href().unwrap();
+        history
+            .replace_state_with_url(&JsValue::NULL, "", Some("/video/42?q=cat"))
+            .unwrap();
+        let auth = Auth::new();
+        mount_to_body(move || {
+            provide_context(auth);
+            view! {
+                <Router>
+                    <LoginRedirect/>
+                </Router>
+            }
+        });
+
+        auth.require("nas".to_string());
+        stand_in::until("the login page", || {
+            location.pathname().unwrap() == "/login"
+        })
+        .await;
+        let search = location.search().unwrap();
+        history
+            .replace_state_with_url(&JsValue::NULL, "", Some(&started_at))
+            .unwrap();
+        assert_eq!(search, "?remote=nas&next=%2Fvideo%2F42%3Fq%3Dcat");
+    }
+}
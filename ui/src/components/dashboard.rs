@@ -1,21 +1,135 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use leptos::*;
 use leptos_router::*;
 
-use crate::{components::ClickToEdit, data::MediaItem, log, MediaUpdate};
+use crate::{
+    client::Client,
+    components::{
+        selection::SelectionToolbar,
+        uploads::{UploadDropZone, UploadPreview, Uploads},
+        ClickToEdit, ConfirmDialog, ErrorMessage,
+    },
+    data::{MediaField, MediaItem, MediaKind, MediaQuery, TrashOp, TrashRequest, PAGE_SIZE},
+    ClientGeneration, EditState, LoadResult, MediaUpdate, PendingUpdate, PendingUpdates, Resync,
+    TrashResult, UpdateResult,
+};
 
 #[cfg(web_sys_unstable_apis)]
 use crate::components::CopyButton;
 
+/// Load the first page of `query` into the store whenever the query or
+/// the client changes, or the library is resynced. Returns whether there
+/// are more pages and a function for fetching the next one.
+pub fn use_paged_media<Q>(query: Q) -> (Signal<bool>, impl Fn() + Copy)
+where
+    Q: Fn() -> MediaQuery + Copy + 'static,
+{
+    let load = use_context::<Action<MediaQuery, LoadResult>>().unwrap();
+    let client = use_context::<RwSignal<Client>>().unwrap();
+    let resync = use_context::<Resync>().unwrap();
+    let generation = use_context::<ClientGeneration>().unwrap();
+    let next = create_rw_signal(None::<String>);
+    create_effect(move |_| {
+        client.track();
+        resync.track();
+        next.set(None);
+        load.dispatch(query());
+    });
+    create_effect(move |_| {
+        if let Some((loaded_with, q, Ok(page))) = load.value().get() {
+            // a cursor from another client would skip items of this one
+            if loaded_with == generation.get_untracked() && q.same_filters(&untrack(query)) {
+                next.set(page.next)
+            }
+        }
+    });
+    let load_more = move || {
+        if load.pending().get_untracked() {
+            return;
+        }
+        if let Some(cursor) = next.get_untracked() {
+            load.dispatch(MediaQuery {
+                cursor: Some(cursor),
+                ..untrack(query)
+            })
+        }
+    };
+    (Signal::derive(move || next.with(Option::is_some)), load_more)
+}
+
 #[component]
-pub fn Selector<F>(path: String, filter: F) -> impl IntoView
+pub fn Selector<F>(
+    path: String,
+    filter: F,
+    /// Prefix each entry with a badge naming its kind
+    #[prop(optional)]
+    show_kind: bool,
+    /// Ask the server for this kind of media only
+    #[prop(optional)]
+    kind: Option<MediaKind>,
+) -> impl IntoView
 where
     F: Fn(String, &MediaItem) -> bool + Copy + 'static,
 {
     let query = use_query_map();
     let search = move || query().get("q").cloned().unwrap_or_default();
     let media = use_context::<ReadSignal<HashMap<String, MediaItem>>>().unwrap();
+    let load = use_context::<Action<MediaQuery, LoadResult>>().unwrap();
+    let load_error = move || {
+        load.value()
+            .get()
+            .and_then(|(_, q, r)| r.err().map(|e| (q, e)))
+    };
+    let (has_more, load_more) = use_paged_media(move || MediaQuery {
+        q: search(),
+        kind,
+        trashed: Some(false),
+        limit: PAGE_SIZE,
+        ..Default::default()
+    });
+    // fetch the next page when scrolled near the bottom of the list
+    let on_scroll = move |e: ev::Event| {
+        let list = event_target::<web_sys::Element>(&e);
+        if list.scroll_top() + list.client_height() >= list.scroll_height() - 64 {
+            load_more()
+        }
+    };
+    let visible = create_memo(move |_| {
+        let mut media = media
+            .get()
+            .into_iter()
+            .filter(|(_, m)| !m.trashed && filter(search(), &&m))
+            .collect::<Vec<_>>();
+        media.sort_by(|(_, a), (_, b)| a.title.cmp(&b.title));
+        media
+    });
+    let selected = create_rw_signal(HashSet::<String>::new());
+    // last checked item, for shift-click range selection
+    let anchor = create_rw_signal(None::<String>);
+    let check = move |id: String, shift: bool| {
+        let range = anchor.get_untracked().filter(|_| shift).and_then(|anchor| {
+            visible.with_untracked(|v| {
+                let a = v.iter().position(|(i, _)| *i == anchor)?;
+                let b = v.iter().position(|(i, _)| *i == id)?;
+                Some(
+                    v[a.min(b)..=a.max(b)]
+                        .iter()
+                        .map(|(i, _)| i.clone())
+                        .collect::<Vec<_>>(),
+                )
+            })
+        });
+        selected.update(|s| match range {
+            Some(ids) => s.extend(ids),
+            None => {
+                if !s.remove(&id) {
+                    s.insert(id.clone());
+                }
+            }
+        });
+        anchor.set(Some(id));
+    };
     view! {
         <Form method="GET" action="." class="search">
             <label>
@@ -23,49 +137,88 @@ where
                 <input type="search" name="q" value=search oninput="this.form.requestSubmit()"/>
             </label>
         </Form>
-        <ul class="selector">
-            <For
-                each=move || {
-                    let mut media = media
-                        .get()
-                        .into_iter()
-                        .filter(|(_, m)| filter(search(), &&m))
-                        .collect::<Vec<_>>();
-                    media.sort_by(|(_, a), (_, b)| a.title.cmp(&b.title));
-                    media
-                }
-
-                key=|(id, _)| id.clone()
-                children=move |(id, item)| {
-                    view! {
-                        <a
-                            title=item.title.clone()
-                            href={
-                                let path = path.clone();
-                                move || crate::path(
-                                    &format!("{}/{}{}", path, id, query().to_query_string()),
-                                )
+        <div class="selector">
+            <SelectionToolbar selected=selected visible=visible/>
+            <ul on:scroll=on_scroll>
+                {move || {
+                    load_error()
+                        .map(|(q, e)| {
+                            view! {
+                                <li>
+                                    <ErrorMessage error=e retry=move || load.dispatch(q.clone())/>
+                                </li>
                             }
-                        >
+                        })
+                }}
+                <For
+                    each=move || visible.get()
+                    key=|(id, _)| id.clone()
+                    children=move |(id, item)| {
+                        let checked = {
+                            let id = id.clone();
+                            move || selected.with(|s| s.contains(&id))
+                        };
+                        view! {
+                            <li class:checked=checked.clone()>
+                                <input
+                                    type="checkbox"
+                                    prop:checked=checked
+                                    on:click={
+                                        let id = id.clone();
+                                        move |e: ev::MouseEvent| check(id.clone(), e.shift_key())
+                                    }
+                                />
 
-                            <li>{item.title}</li>
-                        </a>
+                                <a
+                                    title=item.title.clone()
+                                    href={
+                                        let path = path.clone();
+                                        move || crate::path(
+                                            &format!(
+                                                "{}/{}{}",
+                                                path,
+                                                id,
+                                                query().to_query_string(),
+                                            ),
+                                        )
+                                    }
+                                >
+
+                                    {show_kind
+                                        .then(|| {
+                                            let kind = item.kind();
+                                            view! {
+                                                <span class=format!("kind-badge {}", kind)>
+                                                    {kind.as_str()}
+                                                </span>
+                                            }
+                                        })}
+                                    {item.title}
+                                </a>
+                            </li>
+                        }
                     }
-                }
-            />
+                />
 
-        </ul>
+                <Show when=has_more>
+                    <li class="load-more">
+                        <button on:click=move |_| load_more() disabled=load.pending()>
+                            "Load more"
+                        </button>
+                    </li>
+                </Show>
+            </ul>
+        </div>
         <UploadForm/>
+        <UploadDropZone/>
+        <UploadPreview/>
     }
 }
 
 #[component]
 fn UploadForm() -> impl IntoView {
     let file_input = create_node_ref::<html::Input>();
-    let upload = create_action(|file: &web_sys::File| {
-        let file = file.clone();
-        async move { crate::client::upload_file(file).await }
-    });
+    let uploads = use_context::<Uploads>().unwrap();
     let files = create_rw_signal(Option::<web_sys::FileList>::None);
     let onchange = move |e: ev::Event| {
         let tgt = event_target::<web_sys::HtmlInputElement>(&e);
@@ -75,14 +228,24 @@ fn UploadForm() -> impl IntoView {
     let onsubmit = move |ev: ev::SubmitEvent| {
         ev.prevent_default();
         if let Some(files) = files.get() {
-            for i in 0..files.length() {
-                upload.dispatch(files.get(i).unwrap().clone())
-            }
+            uploads.stage((0..files.length()).filter_map(|i| files.get(i)).collect())
         }
     };
+    let skip_duplicates = uploads.skip_duplicates();
     view! {
         <form class="upload" on:submit=onsubmit>
             <input type="file" multiple on:change=onchange/>
+            <label class="pick-folder">
+                "or a folder " <input type="file" webkitdirectory=true on:change=onchange/>
+            </label>
+            <label class="skip-duplicates">
+                <input
+                    type="checkbox"
+                    prop:checked=skip_duplicates
+                    on:change=move |e| skip_duplicates.set(event_target_checked(&e))
+                />
+                "Skip files the library already has"
+            </label>
             <input node_ref=file_input class="submit" type="submit" value="Upload"/>
         </form>
     }
@@ -91,30 +254,64 @@ fn UploadForm() -> impl IntoView {
 #[component]
 pub fn Editor<R, IV>(render: R) -> impl IntoView
 where
-    R: Fn(String) -> IV + Copy + 'static,
+    R: Fn(String, MediaKind) -> IV + Copy + 'static,
     IV: IntoView,
 {
     let media = use_context::<ReadSignal<HashMap<String, MediaItem>>>().unwrap();
+    let load = use_context::<Action<MediaQuery, LoadResult>>().unwrap();
     let params = use_params_map();
-    let item = move || {
-        let id = params.with(|p| p.get("id").unwrap().clone());
-        media.with(|m| m.get(&id).cloned())
+    let id = move || params.with(|p| p.get("id").cloned().unwrap_or_default());
+    let item = move || media.with(|m| m.get(&id()).cloned());
+    // links can point at items that haven't been paged in yet
+    create_effect(move |_| {
+        let id = id();
+        if media.with_untracked(|m| !m.contains_key(&id)) {
+            load.dispatch(MediaQuery::by_id(id))
+        }
+    });
+    // explain why there's nothing to show once the item has been fetched
+    let missing = move || {
+        if item().is_some() || load.pending().get() {
+            return None;
+        }
+        match load.value().get() {
+            Some((_, q, Err(e))) => Some(
+                view! { <ErrorMessage error=e retry=move || load.dispatch(q.clone())/> }
+                    .into_view(),
+            ),
+            Some(_) => Some(view! { <p>"This item doesn't exist."</p> }.into_view()),
+            None => None,
+        }
     };
-    let url = create_memo(move |_| item().map(|i| i.url));
+    let player = create_memo(move |_| item().map(|i| (i.url.clone(), i.kind())));
     view! {
         <div class="view">
             <Transition fallback=|| {
                 view! { <p>"Loading Video"</p> }
-            }>{move || { url().map(move |url| render(url).into_view()) }}</Transition>
+            }>
+                {move || {
+                    player()
+                        .map(move |(url, kind)| match kind {
+                            MediaKind::Unknown => view! { <NoPlayer url=url/> }.into_view(),
+                            kind => render(url, kind).into_view(),
+                        })
+                }}
+
+            </Transition>
         </div>
         <div class="detail">
+            {missing}
             <Transition fallback=|| {
                 view! { <p>"Loading Video"</p> }
             }>
                 {move || {
                     item()
                         .map(|item| {
-                            view! { <DetailTable item=item/> }
+                            view! {
+                                <DetailTable item=item.clone()/>
+                                <TechnicalInfo item=item.clone()/>
+                                <TrashControls id=item.id title=item.title trashed=item.trashed/>
+                            }
                         })
                 }}
 
@@ -123,28 +320,84 @@ where
     }
 }
 
+/// Render the appropriate HTML5 player for `kind`
+pub fn player(url: String, kind: MediaKind) -> View {
+    match kind {
+        MediaKind::Video => view! {
+            <video controls>
+                <source src=url/>
+            </video>
+        }
+        .into_view(),
+        MediaKind::Image => view! { <img src=url/> }.into_view(),
+        MediaKind::Audio => view! {
+            <audio controls>
+                <source src=url/>
+            </audio>
+        }
+        .into_view(),
+        MediaKind::Unknown => view! { <NoPlayer url=url/> }.into_view(),
+    }
+}
+
+/// Fallback for media the browser can't play
+#[component]
+fn NoPlayer(url: String) -> impl IntoView {
+    view! {
+        <div class="no-player">
+            <p>"There is no player for this format."</p>
+            <p>"You can still download it or edit its details below."</p>
+            <a download href=url>
+                <button>"Download"</button>
+            </a>
+        </div>
+    }
+}
+
+/// Mark a field whose latest edit is waiting for the server or was
+/// rejected by it
+#[component]
+fn EditStatus(id: String, field: &'static str) -> impl IntoView {
+    let pending = use_context::<PendingUpdates>().unwrap();
+    let update = use_context::<Action<MediaUpdate, UpdateResult>>().unwrap();
+    move || match pending.latest(&id, field) {
+        Some(PendingUpdate {
+            state: EditState::Pending,
+            ..
+        }) => view! { <span class="edit-status pending">"saving…"</span> }.into_view(),
+        Some(PendingUpdate {
+            update: u,
+            state: EditState::Failed(e),
+        }) => view! {
+            <span class="edit-status failed">
+                <ErrorMessage error=e retry=move || update.dispatch(u.clone())/>
+            </span>
+        }
+        .into_view(),
+        None => ().into_view(),
+    }
+}
+
 #[allow(unexpected_cfgs)]
 #[component]
 fn DetailTable(item: MediaItem) -> impl IntoView {
     let params = use_params_map();
     let id = move || params.with(|p| p.get("id").unwrap().clone());
-    let update = use_context::<Action<MediaUpdate, Option<MediaUpdate>>>().unwrap();
+    let update = use_context::<Action<MediaUpdate, UpdateResult>>().unwrap();
     view! {
         <table>
             <tr>
                 <td>"title"</td>
                 <td>
-
+                    <EditStatus id=item.id.clone() field="title"/>
                     {view! {
                         <ClickToEdit
                             value=item.title.clone()
                             onset=move |value| {
                                 update
-                                    .dispatch(MediaUpdate {
-                                        id: id(),
-                                        field: "title".to_string(),
-                                        value,
-                                    })
+                                    .dispatch(
+                                        MediaUpdate::new(id(), MediaField::Title(value), None),
+                                    )
                             }
                         />
                     }
@@ -155,17 +408,34 @@ fn DetailTable(item: MediaItem) -> impl IntoView {
             <tr>
                 <td>"format"</td>
                 <td>
-
+                    <EditStatus id=item.id.clone() field="format"/>
                     {view! {
                         <ClickToEdit
                             value=item.format.clone()
                             onset=move |value| {
                                 update
-                                    .dispatch(MediaUpdate {
-                                        id: id(),
-                                        field: "format".to_string(),
-                                        value,
-                                    })
+                                    .dispatch(
+                                        MediaUpdate::new(id(), MediaField::Format(value), None),
+                                    )
+                            }
+                        />
+                    }
+                        .into_view()}
+
+                </td>
+            </tr>
+            <tr>
+                <td>"collection"</td>
+                <td>
+                    <EditStatus id=item.id.clone() field="collection"/>
+                    {view! {
+                        <ClickToEdit
+                            value=item.collection.clone().unwrap_or_default()
+                            onset=move |value| {
+                                update
+                                    .dispatch(
+                                        MediaUpdate::new(id(), MediaField::Collection(value), None),
+                                    )
                             }
                         />
                     }
@@ -174,6 +444,16 @@ fn DetailTable(item: MediaItem) -> impl IntoView {
                 </td>
             </tr>
 
+            {(!item.tags.is_empty())
+                .then(|| {
+                    view! {
+                        <tr>
+                            <td>"tags"</td>
+                            <td class="tags">{item.tags.join(", ")}</td>
+                        </tr>
+                    }
+                })}
+
             <tr>
                 <td>"url"</td>
                 <td>
@@ -202,6 +482,116 @@ fn DetailTable(item: MediaItem) -> impl IntoView {
     }
 }
 
+/// Buttons for moving an item to the trash, or for restoring and
+/// permanently deleting it once it's there
+#[component]
+pub fn TrashControls(id: String, title: String, trashed: bool) -> impl IntoView {
+    let trash = use_context::<Action<TrashRequest, TrashResult>>().unwrap();
+    let confirm = create_rw_signal(false);
+    let dispatch = move |id: &String, op| {
+        trash.dispatch(TrashRequest {
+            id: id.clone(),
+            op,
+            batch: None,
+        })
+    };
+    if trashed {
+        view! {
+            <div class="trash-controls">
+                <span>"In the trash"</span>
+                <button on:click={
+                    let id = id.clone();
+                    move |_| dispatch(&id, TrashOp::Restore)
+                }>"Restore"</button>
+                <button class="danger" on:click=move |_| confirm.set(true)>
+                    "Delete forever"
+                </button>
+                <ConfirmDialog
+                    show=confirm
+                    message=format!("Permanently delete \"{}\"? This can't be undone.", title)
+                    on_confirm=move || dispatch(&id, TrashOp::Delete)
+                />
+            </div>
+        }
+    } else {
+        view! {
+            <div class="trash-controls">
+                <button class="danger" on:click=move |_| confirm.set(true)>
+                    "Delete"
+                </button>
+                <ConfirmDialog
+                    show=confirm
+                    message=format!("Move \"{}\" to the trash?", title)
+                    confirm="Move to trash".to_string()
+                    on_confirm=move || dispatch(&id, TrashOp::Trash)
+                />
+            </div>
+        }
+    }
+}
+
+#[component]
+fn TechnicalInfo(item: MediaItem) -> impl IntoView {
+    let rows = [
+        ("duration", item.duration.map(format_duration)),
+        ("resolution", item.resolution()),
+        ("video codec", item.video_codec.clone()),
+        ("audio codec", item.audio_codec.clone()),
+        ("bitrate", item.bitrate.map(format_bitrate)),
+        ("file size", item.size.map(format_size)),
+        ("added", item.added.clone()),
+    ];
+    view! {
+        <h4>"Technical info"</h4>
+        <table class="technical-info">
+            {rows
+                .into_iter()
+                .map(|(name, value)| {
+                    view! {
+                        <tr>
+                            <td>{name}</td>
+                            <td>{value.unwrap_or_else(|| "-".to_string())}</td>
+                        </tr>
+                    }
+                })
+                .collect_view()}
+        </table>
+    }
+}
+
+pub(crate) fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+fn format_bitrate(bps: u64) -> String {
+    if bps >= 1_000_000 {
+        format!("{:.1} Mb/s", bps as f64 / 1_000_000.0)
+    } else {
+        format!("{} kb/s", bps / 1_000)
+    }
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 fn download_name(item: &MediaItem) -> String {
     if let Some(pos) = item.title.rfind(".") {
         if &item.title[pos..] == &item.format {
@@ -2,6 +2,20 @@ use std::collections::HashMap;
 
 use leptos::*;
 
+/// Handle for posting messages to the [`NotificationTray`]
+#[derive(Clone, Copy)]
+pub struct Notifier(WriteSignal<Option<View>>);
+
+impl Notifier {
+    pub fn new(set_message: WriteSignal<Option<View>>) -> Self {
+        Self(set_message)
+    }
+
+    pub fn notify(&self, message: impl IntoView) {
+        self.0.set(Some(message.into_view()))
+    }
+}
+
 #[component]
 pub fn NotificationTray<F>(message: F) -> impl IntoView
 where
@@ -0,0 +1,41 @@
+use leptos::*;
+
+use crate::remotes::{Remotes, Source};
+
+/// Choose which remote to browse, or all of them at once
+#[component]
+pub fn RemoteSwitcher() -> impl IntoView {
+    let remotes = use_context::<Remotes>().unwrap();
+    let on_change = move |e| {
+        let value = event_target_value(&e);
+        remotes.set_active(match value.as_str() {
+            "demo" => Source::Demo,
+            "merged" => Source::Merged,
+            v => Source::Remote(v.trim_start_matches("remote:").to_string()),
+        })
+    };
+    let option = move |source: Source, label: String| {
+        let value = source.key();
+        view! {
+            <option value=value prop:selected=move || remotes.active() == source>
+                {label}
+            </option>
+        }
+    };
+    view! {
+        <label class="remote-switcher">
+            "Library"
+            <select on:change=on_change>
+                <For
+                    each=move || remotes.list()
+                    key=|remote| (remote.id.clone(), remote.name.clone())
+                    children=move |remote| option(Source::Remote(remote.id), remote.name)
+                />
+                <Show when=move || { remotes.list().len() > 1 }>
+                    {option(Source::Merged, "All remotes".to_string())}
+                </Show>
+                {option(Source::Demo, "Demo data".to_string())}
+            </select>
+        </label>
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+
+use leptos::*;
+
+use crate::{
+    client::ClientError,
+    components::{notification_tray::Notifier, ConfirmDialog},
+    data::{MediaField, MediaItem, TrashOp, TrashRequest},
+    MediaUpdate, TrashResult, UpdateResult,
+};
+
+/// Progress of a bulk operation started from the selection toolbar
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub label: String,
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub last_error: Option<ClientError>,
+}
+
+/// Bulk operations in flight, keyed by batch id
+#[derive(Clone, Copy)]
+pub struct Batches(RwSignal<HashMap<String, Batch>>);
+
+impl Batches {
+    pub fn new() -> Self {
+        Self(create_rw_signal(HashMap::new()))
+    }
+
+    /// Register a bulk operation of `total` requests and return its id
+    pub fn start(&self, label: &str, total: usize) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.0.update(|b| {
+            b.insert(
+                id.clone(),
+                Batch {
+                    label: label.to_string(),
+                    total,
+                    done: 0,
+                    failed: 0,
+                    last_error: None,
+                },
+            );
+        });
+        id
+    }
+
+    /// Record the outcome of one request in `batch`. A single summary is
+    /// posted to the notification tray once every request has finished.
+    pub fn record(&self, batch: &str, result: Result<(), ClientError>, notifier: Notifier) {
+        let mut finished = None;
+        self.0.update(|b| {
+            if let Some(progress) = b.get_mut(batch) {
+                progress.done += 1;
+                if let Err(e) = result {
+                    progress.failed += 1;
+                    progress.last_error = Some(e);
+                }
+                if progress.done >= progress.total {
+                    finished = b.remove(batch);
+                }
+            }
+        });
+        if let Some(batch) = finished {
+            let summary = format!(
+                "{}: {} of {} succeeded",
+                batch.label,
+                batch.total - batch.failed,
+                batch.total
+            );
+            match batch.last_error {
+                Some(e) => notifier.notify(view! {
+                    <p>{summary}</p>
+                    <p class="error-message">{e.to_string()}</p>
+                }),
+                None => notifier.notify(summary),
+            }
+        }
+    }
+
+    pub fn running(&self) -> Vec<(String, Batch)> {
+        self.0.with(|b| b.iter().map(|(id, b)| (id.clone(), b.clone())).collect())
+    }
+}
+
+impl Default for Batches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bulk operations on the items selected in a [`Selector`](super::dashboard::Selector)
+#[component]
+pub fn SelectionToolbar(
+    selected: RwSignal<HashSet<String>>,
+    /// Items currently listed by the selector, in display order
+    visible: Memo<Vec<(String, MediaItem)>>,
+) -> impl IntoView {
+    let media = use_context::<ReadSignal<HashMap<String, MediaItem>>>().unwrap();
+    let update = use_context::<Action<MediaUpdate, UpdateResult>>().unwrap();
+    let trash = use_context::<Action<TrashRequest, TrashResult>>().unwrap();
+    let batches = use_context::<Batches>().unwrap();
+    let format = create_rw_signal(String::new());
+    let collection = create_rw_signal(String::new());
+    let find = create_rw_signal(String::new());
+    let replace = create_rw_signal(String::new());
+    let confirm_delete = create_rw_signal(false);
+
+    let count = move || selected.with(|s| s.len());
+    // selected items that still exist in the library
+    let selection = move || {
+        let ids = selected.get_untracked();
+        media.with_untracked(|m| {
+            ids.iter()
+                .filter_map(|id| m.get(id).cloned())
+                .collect::<Vec<_>>()
+        })
+    };
+    let dispatch = move |label: &str, updates: Vec<(String, MediaField)>| {
+        if updates.is_empty() {
+            return;
+        }
+        let batch = batches.start(label, updates.len());
+        for (id, field) in updates {
+            update.dispatch(MediaUpdate::new(id, field, Some(batch.clone())))
+        }
+    };
+    let set_format = move |_| {
+        let value = format.get_untracked();
+        let updates = selection()
+            .into_iter()
+            .map(|item| (item.id, MediaField::Format(value.clone())))
+            .collect();
+        dispatch("Set format", updates);
+    };
+    let set_collection = move |_| {
+        let value = collection.get_untracked();
+        let updates = selection()
+            .into_iter()
+            .map(|item| (item.id, MediaField::Collection(value.clone())))
+            .collect();
+        dispatch("Add to collection", updates);
+    };
+    let find_replace = move |_| {
+        let (find, replace) = (find.get_untracked(), replace.get_untracked());
+        if find.is_empty() {
+            return;
+        }
+        let updates = selection()
+            .into_iter()
+            .filter(|item| item.title.contains(&find))
+            .map(|item| (item.id, MediaField::Title(item.title.replace(&find, &replace))))
+            .collect();
+        dispatch("Replace in titles", updates);
+    };
+    let delete = move || {
+        let items = selection();
+        if items.is_empty() {
+            return;
+        }
+        let batch = batches.start("Move to trash", items.len());
+        for item in items {
+            trash.dispatch(TrashRequest {
+                id: item.id,
+                op: TrashOp::Trash,
+                batch: Some(batch.clone()),
+            })
+        }
+        selected.update(|s| s.clear());
+    };
+    let select_all = move |_| {
+        selected.update(|s| s.extend(visible.get_untracked().into_iter().map(|(id, _)| id)))
+    };
+
+    view! {
+        <div class="selection-toolbar">
+            <div class="selection-count">
+                {move || format!("{} selected", count())}
+                <button on:click=select_all>"Select all"</button>
+                <button on:click=move |_| selected.update(|s| s.clear())>"Clear"</button>
+            </div>
+            <Show when=move || { count() > 0 }>
+                <label>
+                    <input
+                        type="text"
+                        placeholder="format"
+                        prop:value=format
+                        on:input=move |e| format.set(event_target_value(&e))
+                    />
+                    <button on:click=set_format>"Set format"</button>
+                </label>
+                <label>
+                    <input
+                        type="text"
+                        placeholder="collection"
+                        prop:value=collection
+                        on:input=move |e| collection.set(event_target_value(&e))
+                    />
+                    <button on:click=set_collection>"Add to collection"</button>
+                </label>
+                <label>
+                    <input
+                        type="text"
+                        placeholder="find"
+                        prop:value=find
+                        on:input=move |e| find.set(event_target_value(&e))
+                    />
+                    <input
+                        type="text"
+                        placeholder="replace"
+                        prop:value=replace
+                        on:input=move |e| replace.set(event_target_value(&e))
+                    />
+                    <button on:click=find_replace>"Replace"</button>
+                </label>
+                <button class="danger" on:click=move |_| confirm_delete.set(true)>
+                    "Delete"
+                </button>
+                <ConfirmDialog
+                    show=confirm_delete
+                    message="Move the selected items to the trash?".to_string()
+                    confirm="Move to trash".to_string()
+                    on_confirm=delete
+                />
+            </Show>
+            <For
+                each=move || batches.running()
+                key=|(id, batch)| (id.clone(), batch.done)
+                children=|(_, batch)| {
+                    view! {
+                        <label class="batch-progress">
+                            {batch.label.clone()}
+                            <progress max=batch.total value=batch.done></progress>
+                        </label>
+                    }
+                }
+            />
+
+        </div>
+    }
+}
@@ -0,0 +1,1255 @@
+use leptos::{
+    wasm_bindgen::{JsCast, JsValue},
+    *,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    client::{self, Client, UploadMetadata, UploadPolicy, UploadState, UploadTask},
+    components::{
+        dashboard::{format_duration, format_size},
+        notification_tray::Notifier,
+        ErrorMessage,
+    },
+    data::MediaKind,
+    log,
+    remotes::{Remote, Remotes},
+    storage,
+};
+
+const STORAGE_KEY: &str = "media-manager.uploads";
+
+/// A file being uploaded
+#[derive(Clone)]
+pub struct Upload {
+    pub id: String,
+    /// Remote the file goes to, whichever is browsed by then. None for
+    /// the demo.
+    pub remote: Option<String>,
+    pub file: web_sys::File,
+    pub metadata: UploadMetadata,
+    pub task: UploadTask,
+}
+
+/// An upload that hadn't finished when the page was closed. The file has
+/// to be selected again to resume it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedUpload {
+    pub fingerprint: String,
+    pub name: String,
+    pub size: f64,
+    /// Where the server keeps the upload
+    pub location: String,
+    /// Bytes the server had last time
+    pub offset: f64,
+    /// In case the upload has to be created again
+    #[serde(default)]
+    pub metadata: UploadMetadata,
+    /// Remote the server keeping the upload was reached through. Missing
+    /// from uploads saved before it was recorded, which are resumed with
+    /// the remote browsed.
+    #[serde(default)]
+    pub remote: Option<String>,
+}
+
+/// Tells a file apart from others without reading it
+fn fingerprint(file: &web_sys::File) -> String {
+    format!("{}:{}:{}", file.name(), file.size(), file.last_modified())
+}
+
+/// Uploads started in this session, oldest first, and the ones left
+/// unfinished by earlier sessions. Only as many run at once as the upload
+/// policy allows, the rest are queued.
+#[derive(Clone, Copy)]
+pub struct Uploads {
+    list: RwSignal<Vec<Upload>>,
+    interrupted: RwSignal<Vec<SavedUpload>>,
+    /// Client for the source browsed
+    client: RwSignal<Client>,
+    remotes: Remotes,
+    /// Client for uploads to a remote other than the one browsed
+    remote_client: Callback<Remote, Client>,
+    policy: Signal<UploadPolicy>,
+    /// Files waiting to be previewed and confirmed
+    staged: RwSignal<Vec<Staged>>,
+    /// Whether files the library already has are skipped without asking
+    skip_duplicates: RwSignal<bool>,
+    notifier: Notifier,
+}
+
+impl Uploads {
+    /// Load unfinished uploads from local storage and keep the ones in
+    /// flight saved there
+    pub fn new(
+        client: RwSignal<Client>,
+        remotes: Remotes,
+        remote_client: Callback<Remote, Client>,
+        policy: Signal<UploadPolicy>,
+        notifier: Notifier,
+    ) -> Self {
+        let uploads = Self {
+            list: create_rw_signal(Vec::new()),
+            interrupted: create_rw_signal(storage::load(STORAGE_KEY).unwrap_or_default()),
+            client,
+            remotes,
+            remote_client,
+            policy,
+            staged: create_rw_signal(Vec::new()),
+            skip_duplicates: create_rw_signal(false),
+            notifier,
+        };
+        create_effect(move |_| {
+            let mut saved = uploads
+                .list
+                .with(|l| l.iter().filter_map(in_flight).collect::<Vec<_>>());
+            saved.extend(uploads.interrupted.get());
+            storage::save(STORAGE_KEY, &saved)
+        });
+        // more uploads may run now
+        create_effect(move |_| {
+            policy.track();
+            uploads.next()
+        });
+        uploads
+    }
+
+    pub fn list(&self) -> Vec<Upload> {
+        self.list.get()
+    }
+
+    pub fn interrupted(&self) -> Vec<SavedUpload> {
+        self.interrupted.get()
+    }
+
+    pub fn skip_duplicates(&self) -> RwSignal<bool> {
+        self.skip_duplicates
+    }
+
+    /// Have `files` previewed and catalogued before they are uploaded.
+    /// Files picked with a folder keep their place in it.
+    pub fn stage(&self, files: Vec<web_sys::File>) {
+        self.stage_paths(
+            files
+                .into_iter()
+                .map(|file| (relative_path(&file), file))
+                .collect(),
+        )
+    }
+
+    /// Stage `files` along with their paths in the folder they came from
+    fn stage_paths(&self, files: Vec<(String, web_sys::File)>) {
+        self.staged.update(|s| {
+            s.extend(
+                files
+                    .into_iter()
+                    .map(|(path, file)| Staged::new(file, path)),
+            )
+        })
+    }
+
+    fn edit_staged(&self, id: &str, edit: impl FnOnce(&mut Staged)) {
+        self.staged.update(|s| {
+            if let Some(staged) = s.iter_mut().find(|f| f.id == id) {
+                edit(staged)
+            }
+        })
+    }
+
+    fn unstage(&self, id: &str) {
+        self.staged.update(|s| {
+            s.retain(|f| {
+                let keep = f.id != id;
+                if !keep {
+                    f.release()
+                }
+                keep
+            })
+        })
+    }
+
+    fn clear_staged(&self) {
+        self.staged.update(|s| {
+            for file in s.drain(..) {
+                file.release()
+            }
+        })
+    }
+
+    /// Upload the staged files with the details they were given
+    fn upload_staged(&self) {
+        // a folder can hold hundreds of files
+        batch(|| {
+            for staged in self.staged.get_untracked() {
+                let metadata = UploadMetadata {
+                    relative_path: staged.path,
+                    ..UploadMetadata::new(&staged.title, &staged.tags, &staged.folder)
+                };
+                self.start(staged.file, metadata)
+            }
+        });
+        self.clear_staged()
+    }
+
+    /// Queue `file` for upload to the remote browsed, picking up where an
+    /// earlier session left off if it was interrupted, on the remote it
+    /// was going to. New uploads are checked for duplicates first.
+    pub fn start(&self, file: web_sys::File, metadata: UploadMetadata) {
+        let task = UploadTask::new(file.size(), self.policy);
+        let fingerprint = fingerprint(&file);
+        let saved = self.take_interrupted(&fingerprint);
+        let target = self.remotes.upload_target().map(|r| r.id);
+        let (remote, metadata) = match saved {
+            Some(saved) => {
+                task.location.set(Some(saved.location));
+                task.sent.set(saved.offset);
+                // the server already has the upload with these
+                (saved.remote.or(target), saved.metadata)
+            }
+            None => (target, metadata),
+        };
+        let upload = Upload {
+            id: uuid::Uuid::new_v4().to_string(),
+            remote,
+            task,
+            file,
+            metadata,
+        };
+        self.list.update(|l| l.push(upload.clone()));
+        self.enqueue(&upload)
+    }
+
+    fn enqueue(&self, upload: &Upload) {
+        upload.task.state.set(UploadState::Queued);
+        self.next()
+    }
+
+    /// Start queued uploads, oldest first, while fewer than the allowed
+    /// number are running
+    fn next(&self) {
+        let concurrency = self.policy.get_untracked().concurrency;
+        let queued = self.list.with_untracked(|l| {
+            let running = l
+                .iter()
+                .filter(|u| is_running(&u.task.state.get_untracked()))
+                .count();
+            l.iter()
+                .filter(|u| u.task.state.get_untracked() == UploadState::Queued)
+                .take(concurrency.saturating_sub(running))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        for upload in queued {
+            let task = upload.task;
+            // neither checked nor known to the server yet
+            if task.sha256.get_untracked().is_none() && task.location.get_untracked().is_none() {
+                self.check(upload)
+            } else {
+                self.run(upload)
+            }
+        }
+    }
+
+    /// Client for uploads to `remote`. The remote browsed already has one;
+    /// others get one of their own, with the token they have now.
+    fn client_for(&self, remote: &Option<String>) -> client::Result<Client> {
+        if *remote == self.remotes.upload_target().map(|r| r.id) {
+            return Ok(self.client.get_untracked());
+        }
+        let id = remote.as_deref().unwrap_or_default();
+        match self.remotes.get(id) {
+            Some(remote) => Ok((self.remote_client)(remote)),
+            None => Err(client::ClientError::Invalid(format!(
+                "remote {} was removed",
+                id
+            ))),
+        }
+    }
+
+    /// Hash `upload` and upload it unless the library already has it. If
+    /// the check itself fails the file is uploaded regardless.
+    fn check(&self, upload: Upload) {
+        let uploads = *self;
+        let task = upload.task;
+        let client = match self.client_for(&upload.remote) {
+            Ok(client) => client,
+            Err(e) => {
+                task.state.set(UploadState::Failed(e));
+                return self.next();
+            }
+        };
+        task.state.set(UploadState::Checking);
+        spawn_local(async move {
+            let duplicate = uploads.duplicate_of(client, &upload).await;
+            // cancelled while checking
+            if task.state.get_untracked() != UploadState::Checking {
+                return uploads.next();
+            }
+            match duplicate {
+                Ok(None) => uploads.run(upload),
+                Ok(Some(title)) if uploads.skip_duplicates.get_untracked() => {
+                    task.state.set(UploadState::Cancelled);
+                    uploads.notifier.notify(format!(
+                        "Skipped {}, a duplicate of {}",
+                        upload.file.name(),
+                        title
+                    ));
+                    uploads.next()
+                }
+                Ok(Some(title)) => {
+                    task.state.set(UploadState::Duplicate(title));
+                    uploads.next()
+                }
+                Err(e) => {
+                    log!(
+                        "couldn't check {} for duplicates: {}",
+                        upload.file.name(),
+                        e
+                    );
+                    uploads.run(upload)
+                }
+            }
+        })
+    }
+
+    /// Title of what `upload` would duplicate: an earlier upload of the
+    /// same file in this session, or an item in the library
+    async fn duplicate_of(
+        &self,
+        client: Client,
+        upload: &Upload,
+    ) -> client::Result<Option<String>> {
+        let task = upload.task;
+        let sha256 = client::sha256(&upload.file, |hashed| task.checked.set(hashed)).await?;
+        task.sha256.set(Some(sha256.clone()));
+        let earlier = self.list.with_untracked(|l| {
+            l.iter()
+                .filter(|u| u.id != upload.id)
+                .filter(|u| u.task.sha256.get_untracked().as_ref() == Some(&sha256))
+                .find(|u| {
+                    !matches!(
+                        u.task.state.get_untracked(),
+                        UploadState::Cancelled | UploadState::Duplicate(_) | UploadState::Failed(_)
+                    )
+                })
+                .map(|u| u.file.name())
+        });
+        if earlier.is_some() {
+            return Ok(earlier);
+        }
+        Ok(client.find_by_hash(sha256).await?.map(|item| {
+            if item.trashed {
+                format!("{} (in the trash)", item.title)
+            } else {
+                item.title
+            }
+        }))
+    }
+
+    /// Resume interrupted upload `fingerprint` with `file`. Returns whether
+    /// `file` is the one that was being uploaded.
+    pub fn resume_interrupted(&self, fingerprint: &str, file: web_sys::File) -> bool {
+        let matches = self::fingerprint(&file) == fingerprint;
+        if matches {
+            self.start(file, UploadMetadata::default())
+        }
+        matches
+    }
+
+    /// Forget interrupted upload `fingerprint`
+    pub fn discard(&self, fingerprint: &str) {
+        self.take_interrupted(fingerprint);
+    }
+
+    fn take_interrupted(&self, fingerprint: &str) -> Option<SavedUpload> {
+        let mut taken = None;
+        self.interrupted.update(|i| {
+            if let Some(pos) = i.iter().position(|s| s.fingerprint == fingerprint) {
+                taken = Some(i.remove(pos))
+            }
+        });
+        taken
+    }
+
+    fn run(&self, upload: Upload) {
+        let uploads = *self;
+        let task = upload.task;
+        let client = match self.client_for(&upload.remote) {
+            Ok(client) => client,
+            Err(e) => {
+                task.state.set(UploadState::Failed(e));
+                return self.next();
+            }
+        };
+        task.state.set(UploadState::Uploading);
+        spawn_local(async move {
+            let result = client.upload_file(upload.file, upload.metadata, task).await;
+            task.state.update(|state| match result {
+                // a cancelled upload ends without error
+                _ if *state == UploadState::Cancelled => {}
+                Ok(()) => *state = UploadState::Done,
+                Err(e) => *state = UploadState::Failed(e),
+            });
+            uploads.next()
+        })
+    }
+
+    /// Queue a failed upload again, to continue where the server left off
+    pub fn retry(&self, id: &str) {
+        if let Some(upload) = self.find(id) {
+            self.enqueue(&upload)
+        }
+    }
+
+    /// Upload a file the library already has
+    pub fn upload_anyway(&self, id: &str) {
+        if let Some(upload) = self.find(id) {
+            self.enqueue(&upload)
+        }
+    }
+
+    pub fn pause(&self, id: &str) {
+        self.set_state(id, UploadState::Paused)
+    }
+
+    pub fn resume(&self, id: &str) {
+        self.set_state(id, UploadState::Uploading)
+    }
+
+    pub fn cancel(&self, id: &str) {
+        self.set_state(id, UploadState::Cancelled)
+    }
+
+    /// Forget uploads that are done or cancelled
+    pub fn clear_finished(&self) {
+        self.list.update(|l| {
+            l.retain(|u| {
+                !matches!(
+                    u.task.state.get_untracked(),
+                    UploadState::Done | UploadState::Cancelled
+                )
+            })
+        })
+    }
+
+    fn find(&self, id: &str) -> Option<Upload> {
+        self.list
+            .with_untracked(|l| l.iter().find(|u| u.id == id).cloned())
+    }
+
+    fn set_state(&self, id: &str, state: UploadState) {
+        if let Some(upload) = self.find(id) {
+            upload.task.state.set(state)
+        }
+    }
+}
+
+/// Whether an upload in `state` takes up one of the slots the upload policy
+/// allows. Paused uploads keep theirs so resuming them doesn't go over.
+fn is_running(state: &UploadState) -> bool {
+    matches!(
+        state,
+        UploadState::Checking | UploadState::Uploading | UploadState::Paused
+    )
+}
+
+/// Record to save for `upload` if it is unfinished and known to the server
+fn in_flight(upload: &Upload) -> Option<SavedUpload> {
+    let task = upload.task;
+    if matches!(task.state.get(), UploadState::Done | UploadState::Cancelled) {
+        return None;
+    }
+    Some(SavedUpload {
+        fingerprint: fingerprint(&upload.file),
+        name: upload.file.name(),
+        size: task.size,
+        location: task.location.get()?,
+        offset: task.sent.get(),
+        metadata: upload.metadata.clone(),
+        remote: upload.remote.clone(),
+    })
+}
+
+/// Progress of every upload, with controls to steer them
+#[component]
+pub fn UploadManager() -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let visible =
+        move || !uploads.list.with(Vec::is_empty) || !uploads.interrupted.with(Vec::is_empty);
+    view! {
+        <Show when=visible>
+            <div id="upload-manager">
+                <h3>"Uploads"</h3>
+                <For
+                    each=move || uploads.interrupted()
+                    key=|saved| saved.fingerprint.clone()
+                    children=move |saved| view! { <InterruptedRow saved=saved/> }
+                />
+                <FolderContents path=String::new()/>
+                <button on:click=move |_| uploads.clear_finished()>"Clear finished"</button>
+            </div>
+        </Show>
+    }
+}
+
+/// Uploads in the folder at `path`, as subfolders followed by files. The
+/// root is the empty path.
+#[component]
+fn FolderContents(path: String) -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let depth = path.split('/').filter(|p| !p.is_empty()).count();
+    let path = store_value(path);
+    let subfolders = move || {
+        let mut folders = uploads.list.with(|l| {
+            l.iter()
+                .filter_map(|u| {
+                    let folders = u.metadata.folders();
+                    let inside = path.with_value(|p| in_folder(&folders, p));
+                    (inside && folders.len() > depth).then(|| folders[..=depth].join("/"))
+                })
+                .collect::<Vec<_>>()
+        });
+        folders.sort();
+        folders.dedup();
+        folders
+    };
+    let files = move || {
+        uploads.list.with(|l| {
+            l.iter()
+                .filter(|u| {
+                    let folders = u.metadata.folders();
+                    folders.len() == depth && path.with_value(|p| in_folder(&folders, p))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+    view! {
+        <For
+            each=subfolders
+            key=|folder| folder.clone()
+            // a view rather than the component's own type, which would nest
+            // in itself
+            children=move |folder| view! { <UploadFolder path=folder/> }.into_view()
+        />
+        <For
+            each=files
+            key=|upload| upload.id.clone()
+            children=move |upload| view! { <UploadRow upload=upload/> }
+        />
+    }
+}
+
+/// A folder that was picked or dropped, with the progress of everything
+/// in it
+#[component]
+fn UploadFolder(path: String) -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let name = path.rsplit('/').next().unwrap_or_default().to_string();
+    let folder = store_value(path.clone());
+    let tasks = move || {
+        uploads.list.with(|l| {
+            l.iter()
+                .filter(|u| folder.with_value(|f| in_folder(&u.metadata.folders(), f)))
+                .map(|u| u.task)
+                .collect::<Vec<_>>()
+        })
+    };
+    let size = move || tasks().iter().map(|t| t.size).sum::<f64>();
+    let sent = move || tasks().iter().map(|t| t.sent.get()).sum::<f64>();
+    let status = move || {
+        let tasks = tasks();
+        let done = tasks
+            .iter()
+            .filter(|t| t.state.get() == UploadState::Done)
+            .count();
+        format!(
+            "{} of {} files, {} of {}",
+            done,
+            tasks.len(),
+            format_size(sent() as u64),
+            format_size(size() as u64)
+        )
+    };
+    view! {
+        <details class="upload-folder" open>
+            <summary>
+                <span class="upload-name">{name} "/"</span>
+                <progress max=size value=sent></progress>
+                <span class="upload-status">{status}</span>
+            </summary>
+            <FolderContents path=path/>
+        </details>
+    }
+}
+
+/// Whether `folders` lie within `path`
+fn in_folder(folders: &[&str], path: &str) -> bool {
+    path.is_empty() || {
+        let path = path.split('/').collect::<Vec<_>>();
+        folders.starts_with(&path)
+    }
+}
+
+/// An upload from an earlier session, resumed by selecting its file again
+#[component]
+fn InterruptedRow(saved: SavedUpload) -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let notifier = use_context::<Notifier>().unwrap();
+    let fingerprint = store_value(saved.fingerprint);
+    let name = saved.name.clone();
+    let on_change = move |e: ev::Event| {
+        let input = event_target::<web_sys::HtmlInputElement>(&e);
+        let Some(file) = input.files().and_then(|f| f.get(0)) else {
+            return;
+        };
+        if !fingerprint.with_value(|f| uploads.resume_interrupted(f, file)) {
+            notifier.notify(format!("Select {} to resume its upload", name))
+        }
+    };
+    view! {
+        <div class="upload interrupted">
+            <span class="upload-name">{saved.name}</span>
+            <progress max=saved.size value=saved.offset></progress>
+            <span class="upload-status">
+                {format!(
+                    "interrupted at {} of {}, select the file again to resume",
+                    format_size(saved.offset as u64),
+                    format_size(saved.size as u64),
+                )}
+            </span>
+            <label class="resume-upload">
+                "Resume" <input type="file" on:change=on_change/>
+            </label>
+            <button on:click=move |_| fingerprint.with_value(|f| uploads.discard(f))>
+                "Discard"
+            </button>
+        </div>
+    }
+}
+
+#[component]
+fn UploadRow(upload: Upload) -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let task = upload.task;
+    let id = store_value(upload.id);
+    let state = move || task.state.get();
+    let size = format_size(task.size as u64);
+    let status = move || match state() {
+        UploadState::Queued => "queued".to_string(),
+        UploadState::Checking => format!(
+            "checking for duplicates, {:.0}%",
+            100.0 * task.checked.get() / task.size.max(1.0)
+        ),
+        UploadState::Duplicate(title) => format!("duplicate of {}", title),
+        UploadState::Uploading => {
+            let rate = task.rate.get();
+            let eta = task.eta().map(format_duration).unwrap_or("-".to_string());
+            format!(
+                "{} of {}, {}/s, {} left",
+                format_size(task.sent.get() as u64),
+                size,
+                format_size(rate as u64),
+                eta
+            )
+        }
+        UploadState::Paused => format!("paused at {}", format_size(task.sent.get() as u64)),
+        UploadState::Cancelled => "cancelled".to_string(),
+        UploadState::Done => format!("{} uploaded", size),
+        UploadState::Failed(_) => "failed".to_string(),
+    };
+    let active = move || matches!(state(), UploadState::Uploading | UploadState::Paused);
+    let progress = move || match state() {
+        UploadState::Checking => task.checked.get(),
+        _ => task.sent.get(),
+    };
+    view! {
+        <div class=move || format!("upload {}", state_class(&state()))>
+            <span class="upload-name">{upload.file.name()}</span>
+            <progress max=task.size value=progress></progress>
+            <span class="upload-status">{status}</span>
+            <Show when=move || matches!(state(), UploadState::Queued | UploadState::Checking)>
+                <button
+                    class="danger"
+                    on:click=move |_| id.with_value(|id| uploads.cancel(id))
+                >
+                    "Cancel"
+                </button>
+            </Show>
+            <Show when=move || matches!(state(), UploadState::Duplicate(_))>
+                <button on:click=move |_| {
+                    id.with_value(|id| uploads.upload_anyway(id))
+                }>"Upload anyway"</button>
+                <button on:click=move |_| id.with_value(|id| uploads.cancel(id))>"Skip"</button>
+            </Show>
+            <Show when=active>
+                {move || match state() {
+                    UploadState::Paused => {
+                        view! {
+                            <button on:click=move |_| {
+                                id.with_value(|id| uploads.resume(id))
+                            }>"Resume"</button>
+                        }
+                    }
+                    _ => {
+                        view! {
+                            <button on:click=move |_| {
+                                id.with_value(|id| uploads.pause(id))
+                            }>"Pause"</button>
+                        }
+                    }
+                }}
+                <button
+                    class="danger"
+                    on:click=move |_| id.with_value(|id| uploads.cancel(id))
+                >
+                    "Cancel"
+                </button>
+            </Show>
+            {move || match state() {
+                UploadState::Failed(e) => {
+                    view! {
+                        <ErrorMessage
+                            error=e
+                            retry=move || id.with_value(|id| uploads.retry(id))
+                        />
+                    }
+                        .into_view()
+                }
+                _ => view! {}.into_view(),
+            }}
+        </div>
+    }
+}
+
+/// A file waiting to be confirmed, with the details it will be uploaded
+/// with as they are typed
+#[derive(Clone)]
+struct Staged {
+    id: String,
+    file: web_sys::File,
+    /// Object URL the preview is shown from
+    url: String,
+    /// Where the file is in the folder it came with, if any
+    path: String,
+    title: String,
+    /// Comma-separated
+    tags: String,
+    folder: String,
+}
+
+impl Staged {
+    fn new(file: web_sys::File, path: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: web_sys::Url::create_object_url_with_blob(&file).unwrap_or_default(),
+            file,
+            path,
+            title: String::new(),
+            tags: String::new(),
+            folder: String::new(),
+        }
+    }
+
+    fn release(&self) {
+        let _ = web_sys::Url::revoke_object_url(&self.url);
+    }
+}
+
+/// Accept files dropped anywhere on the page or pasted from the
+/// clipboard and stage them for the [`UploadPreview`]. Listens for as long
+/// as it is mounted.
+#[component]
+pub fn UploadDropZone() -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let dragging = create_rw_signal(false);
+    let _ = leptos_use::use_event_listener(window(), ev::dragover, move |e| {
+        if carries_files(e.data_transfer()) {
+            e.prevent_default();
+            dragging.set(true)
+        }
+    });
+    let _ = leptos_use::use_event_listener(window(), ev::dragleave, move |e| {
+        // leaving the window rather than moving between elements
+        if e.related_target().is_none() {
+            dragging.set(false)
+        }
+    });
+    let _ = leptos_use::use_event_listener(window(), ev::drop, move |e| {
+        dragging.set(false);
+        // entries have to be taken before the handler returns, but folders
+        // can only be read asynchronously
+        let entries = entries_of(e.data_transfer());
+        if entries.iter().any(|e| e.is_directory()) {
+            e.prevent_default();
+            spawn_local(async move {
+                match files_in(entries).await {
+                    Ok(files) => uploads.stage_paths(files),
+                    Err(e) => log!("failed to read dropped folder: {:?}", e),
+                }
+            });
+            return;
+        }
+        let files = files_of(e.data_transfer());
+        if !files.is_empty() {
+            e.prevent_default();
+            uploads.stage(files)
+        }
+    });
+    let _ = leptos_use::use_event_listener(window(), ev::paste, move |e| {
+        let files = files_of(
+            e.unchecked_ref::<web_sys::ClipboardEvent>()
+                .clipboard_data(),
+        )
+        .into_iter()
+        .map(|f| pasted(&f).unwrap_or(f))
+        .collect::<Vec<_>>();
+        if !files.is_empty() {
+            e.prevent_default();
+            uploads.stage(files)
+        }
+    });
+    view! {
+        <Show when=dragging>
+            <div class="drop-overlay">"Drop files to upload them"</div>
+        </Show>
+    }
+}
+
+/// Staged files, each with a form for the title, tags and folder it is
+/// catalogued with, and buttons to upload them or drop them
+#[component]
+pub fn UploadPreview() -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    on_cleanup(move || uploads.clear_staged());
+    view! {
+        <Show when=move || !uploads.staged.with(Vec::is_empty)>
+            <div class="dialog-backdrop">
+                <div class="dialog upload-preview" role="dialog">
+                    <h3>"Upload these files?"</h3>
+                    <ul>
+                        <For
+                            each=move || uploads.staged.get()
+                            key=|f| f.id.clone()
+                            children=move |f| view! { <StagedRow staged=f/> }
+                        />
+                    </ul>
+                    <div class="dialog-buttons">
+                        <button on:click=move |_| uploads.clear_staged()>"Cancel"</button>
+                        <button on:click=move |_| uploads.upload_staged()>"Upload"</button>
+                    </div>
+                </div>
+            </div>
+        </Show>
+    }
+}
+
+#[component]
+fn StagedRow(staged: Staged) -> impl IntoView {
+    let uploads = use_context::<Uploads>().unwrap();
+    let id = store_value(staged.id.clone());
+    let name = staged.file.name();
+    let shown = if staged.path.is_empty() {
+        name.clone()
+    } else {
+        staged.path.clone()
+    };
+    let stem = name
+        .rsplit_once('.')
+        .map_or(name.clone(), |(stem, _)| stem.to_string());
+    let edit = move |e: ev::Event, field: fn(&mut Staged) -> &mut String| {
+        let value = event_target_value(&e);
+        id.with_value(|id| uploads.edit_staged(id, |s| *field(s) = value))
+    };
+    view! {
+        <li>
+            {preview(&staged)}
+            <span class="upload-name">{shown}</span>
+            <span>{format_size(staged.file.size() as u64)}</span>
+            <button on:click=move |_| id.with_value(|id| uploads.unstage(id))>"Remove"</button>
+            <div class="upload-details">
+                <input
+                    type="text"
+                    placeholder=stem
+                    prop:value=staged.title
+                    on:input=move |e| edit(e, |s| &mut s.title)
+                />
+                <input
+                    type="text"
+                    placeholder="tags, comma separated"
+                    prop:value=staged.tags
+                    on:input=move |e| edit(e, |s| &mut s.tags)
+                />
+                <input
+                    type="text"
+                    placeholder="folder, e.g. holidays/2024"
+                    prop:value=staged.folder
+                    on:input=move |e| edit(e, |s| &mut s.folder)
+                />
+            </div>
+        </li>
+    }
+}
+
+fn preview(staged: &Staged) -> View {
+    let extension = staged
+        .file
+        .name()
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_string());
+    let url = staged.url.clone();
+    match MediaKind::from_format(extension.as_deref().unwrap_or_default()) {
+        MediaKind::Image => view! { <img class="preview" src=url/> }.into_view(),
+        MediaKind::Video => view! { <video class="preview" src=url muted></video> }.into_view(),
+        MediaKind::Audio => view! { <audio class="preview" src=url controls></audio> }.into_view(),
+        MediaKind::Unknown => view! { <span class="preview"></span> }.into_view(),
+    }
+}
+
+fn carries_files(data: Option<web_sys::DataTransfer>) -> bool {
+    data.is_some_and(|d| d.types().includes(&"Files".into(), 0))
+}
+
+fn files_of(data: Option<web_sys::DataTransfer>) -> Vec<web_sys::File> {
+    let Some(files) = data.and_then(|d| d.files()) else {
+        return Vec::new();
+    };
+    (0..files.length()).filter_map(|i| files.get(i)).collect()
+}
+
+/// Where `file` is in the folder it was picked with, if it was
+fn relative_path(file: &web_sys::File) -> String {
+    js_sys::Reflect::get(file, &"webkitRelativePath".into())
+        .ok()
+        .and_then(|p| p.as_string())
+        .filter(|p| p.contains('/'))
+        .unwrap_or_default()
+}
+
+fn entries_of(data: Option<web_sys::DataTransfer>) -> Vec<web_sys::FileSystemEntry> {
+    let Some(items) = data.map(|d| d.items()) else {
+        return Vec::new();
+    };
+    (0..items.length())
+        .filter_map(|i| items.get(i)?.webkit_get_as_entry().ok().flatten())
+        .collect()
+}
+
+/// Every file under `entries`, with its path relative to where it was
+/// dropped, in path order
+async fn files_in(
+    entries: Vec<web_sys::FileSystemEntry>,
+) -> Result<Vec<(String, web_sys::File)>, JsValue> {
+    let mut pending = entries;
+    let mut files = Vec::new();
+    while let Some(entry) = pending.pop() {
+        if entry.is_directory() {
+            let reader = entry
+                .unchecked_ref::<web_sys::FileSystemDirectoryEntry>()
+                .create_reader();
+            // entries come in batches until an empty one
+            loop {
+                let batch = JsFuture::from(js_sys::Promise::new(&mut |resolve, reject| {
+                    if let Err(e) =
+                        reader.read_entries_with_callback_and_callback(&resolve, &reject)
+                    {
+                        let _ = reject.call1(&JsValue::NULL, &e);
+                    }
+                }))
+                .await?;
+                let batch = js_sys::Array::from(&batch);
+                if batch.length() == 0 {
+                    break;
+                }
+                pending.extend(batch.iter().map(JsCast::unchecked_into));
+            }
+        } else {
+            let file = JsFuture::from(js_sys::Promise::new(&mut |resolve, reject| {
+                entry
+                    .unchecked_ref::<web_sys::FileSystemFileEntry>()
+                    .file_with_callback_and_callback(&resolve, &reject)
+            }))
+            .await?;
+            let path = entry.full_path().trim_start_matches('/').to_string();
+            let path = if path.contains('/') {
+                path
+            } else {
+                String::new()
+            };
+            files.push((path, file.unchecked_into()));
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+/// Copy of a pasted file with a name of its own. Browsers name every
+/// pasted image `image.png`, so uploads would overwrite each other.
+fn pasted(file: &web_sys::File) -> Option<web_sys::File> {
+    let extension = file.name().rsplit_once('.').map(|(_, e)| e.to_string())?;
+    let stamp = String::from(js_sys::Date::new_0().to_iso_string()).replace(':', "-");
+    let mut options = web_sys::FilePropertyBag::new();
+    options.type_(&file.type_());
+    web_sys::File::new_with_blob_sequence_and_options(
+        &js_sys::Array::of1(file),
+        &format!("pasted-{}.{}", stamp, extension),
+        &options,
+    )
+    .ok()
+}
+
+fn state_class(state: &UploadState) -> &'static str {
+    match state {
+        UploadState::Queued => "queued",
+        UploadState::Checking => "checking",
+        UploadState::Duplicate(_) => "duplicate",
+        UploadState::Uploading => "uploading",
+        UploadState::Paused => "paused",
+        UploadState::Cancelled => "cancelled",
+        UploadState::Done => "done",
+        UploadState::Failed(_) => "failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::client::{stand_in, ApiClient, TransportKind};
+
+    /// Uploads to the stand-in while browsing the default remote of
+    /// `remotes`, without a token
+    fn uploads(remotes: Remotes, policy: Signal<UploadPolicy>) -> Uploads {
+        let client: Client = Rc::new(ApiClient::new(stand_in::BASE, None, TransportKind::Rest));
+        Uploads::new(
+            create_rw_signal(client),
+            remotes,
+            Callback::new(|r: Remote| -> Client {
+                Rc::new(ApiClient::new(&r.base_url, r.token, TransportKind::Rest))
+            }),
+            policy,
+            Notifier::new(create_signal(None).1),
+        )
+    }
+
+    fn file(name: &str, contents: &str) -> web_sys::File {
+        let parts = js_sys::Array::of1(&contents.into());
+        web_sys::File::new_with_str_sequence(&parts, name).unwrap()
+    }
+
+    /// Start without remotes or uploads saved by earlier tests
+    fn clear_storage() {
+        window().local_storage().unwrap().unwrap().clear().unwrap()
+    }
+
+    /// Answer tus as a server taking 5-byte uploads at `/files/1`
+    fn accept_uploads() {
+        stand_in::respond_with(
+            "POST",
+            "/files",
+            201,
+            "",
+            &[("Location", "/files/1")],
+            Duration::ZERO,
+        );
+        stand_in::respond_with(
+            "PATCH",
+            "/files/1",
+            204,
+            "",
+            &[("Upload-Offset", "5")],
+            Duration::ZERO,
+        );
+    }
+
+    /// Wait for `task` to leave the queue and the duplicate check
+    async fn until_checked(task: UploadTask) -> UploadState {
+        stand_in::until("the file to be checked", move || {
+            !matches!(
+                task.state.get_untracked(),
+                UploadState::Queued | UploadState::Checking
+            )
+        })
+        .await;
+        task.state.get_untracked()
+    }
+
+    #[wasm_bindgen_test]
+    async fn flags_files_already_uploaded_in_the_session() {
+        stand_in::reset();
+        clear_storage();
+        accept_uploads();
+        let uploads = uploads(Remotes::new(), Signal::derive(UploadPolicy::default));
+        let start = |name, contents| {
+            uploads.start(file(name, contents), UploadMetadata::default());
+            uploads.list().last().unwrap().task
+        };
+
+        let first = start("a.txt", "hello");
+        stand_in::until("the first upload to finish", move || {
+            first.state.get_untracked() == UploadState::Done
+        })
+        .await;
+        let same = start("b.txt", "hello");
+        let other = start("c.txt", "other");
+        assert_eq!(
+            until_checked(same).await,
+            UploadState::Duplicate("a.txt".to_string())
+        );
+        // and nothing is left in flight for the next test
+        stand_in::until("the other file to be uploaded", move || {
+            other.state.get_untracked() == UploadState::Done
+        })
+        .await;
+
+        uploads.skip_duplicates().set(true);
+        let skipped = start("d.txt", "hello");
+        assert_eq!(until_checked(skipped).await, UploadState::Cancelled);
+    }
+
+    #[wasm_bindgen_test]
+    async fn resumes_interrupted_uploads_on_their_own_remote() {
+        stand_in::reset();
+        clear_storage();
+        let remotes = Remotes::new();
+        remotes.add(
+            "NAS".to_string(),
+            stand_in::BASE.to_string(),
+            Some("nas-token".to_string()),
+        );
+        let nas = remotes
+            .list()
+            .into_iter()
+            .find(|r| r.name == "NAS")
+            .unwrap();
+        let file = file("hello.txt", "hello");
+        storage::save(
+            STORAGE_KEY,
+            &[SavedUpload {
+                fingerprint: fingerprint(&file),
+                name: file.name(),
+                size: file.size(),
+                location: format!("{}/files/1", stand_in::BASE),
+                offset: 0.0,
+                metadata: UploadMetadata::default(),
+                remote: Some(nas.id),
+            }],
+        );
+        let offset = |o| [("Upload-Offset", o)];
+        stand_in::respond_with("HEAD", "/files/1", 200, "", &offset("0"), Duration::ZERO);
+        stand_in::respond_with("PATCH", "/files/1", 204, "", &offset("5"), Duration::ZERO);
+
+        let uploads = uploads(remotes, Signal::derive(UploadPolicy::default));
+        assert!(uploads.resume_interrupted(&fingerprint(&file), file));
+        let task = uploads.list()[0].task;
+        stand_in::until("the upload to finish", move || {
+            task.state.get_untracked() == UploadState::Done
+        })
+        .await;
+        let requests = stand_in::requests();
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert_eq!(
+                request.headers.get("authorization").map(String::as_str),
+                Some("Bearer nas-token")
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    async fn runs_only_as_many_uploads_as_the_policy_allows() {
+        stand_in::reset();
+        clear_storage();
+        stand_in::respond_with(
+            "POST",
+            "/files",
+            201,
+            "",
+            &[("Location", "/files/1")],
+            Duration::ZERO,
+        );
+        // slow enough to pause an upload with a chunk in flight
+        stand_in::respond_with(
+            "PATCH",
+            "/files/1",
+            204,
+            "",
+            &[],
+            Duration::from_millis(200),
+        );
+        let policy = create_rw_signal(UploadPolicy {
+            concurrency: 1,
+            chunk_size: 5.0,
+            bandwidth: None,
+        });
+        let uploads = uploads(Remotes::new(), policy.into());
+        let start = |name, contents| {
+            uploads.start(file(name, contents), UploadMetadata::default());
+            uploads.list().pop().unwrap()
+        };
+        let patches = || {
+            stand_in::requests()
+                .iter()
+                .filter(|r| r.method == "PATCH")
+                .count()
+        };
+
+        let first = start("a.txt", "fifteen bytes!!");
+        stand_in::until("the first chunk to be sent", move || patches() == 1).await;
+        uploads.pause(&first.id);
+        let second = start("b.txt", "fifteen more!!!");
+        stand_in::until("the chunk in flight to arrive", move || {
+            first.task.sent.get_untracked() == 5.0
+        })
+        .await;
+        // the paused upload keeps its slot
+        assert_eq!(second.task.state.get_untracked(), UploadState::Queued);
+
+        policy.set(UploadPolicy {
+            concurrency: 2,
+            chunk_size: 10.0,
+            bandwidth: None,
+        });
+        stand_in::until("the second upload to start", move || {
+            second.task.state.get_untracked() != UploadState::Queued
+        })
+        .await;
+        uploads.resume(&first.id);
+        stand_in::until("both uploads to finish", move || {
+            [first.task, second.task]
+                .iter()
+                .all(|t| t.state.get_untracked() == UploadState::Done)
+        })
+        .await;
+        // the rest of the first file went in one of the bigger chunks
+        assert_eq!(patches(), 4);
+    }
+
+    #[wasm_bindgen_test]
+    fn groups_uploads_by_the_folders_they_came_in() {
+        let picked = |path: &str| UploadMetadata {
+            relative_path: path.to_string(),
+            ..UploadMetadata::default()
+        };
+        assert!(picked("").folders().is_empty());
+        assert!(picked("sea.jpg").folders().is_empty());
+        let nested = picked("trips/2024/sea.jpg");
+        let folders = nested.folders();
+        assert_eq!(folders, vec!["trips", "2024"]);
+
+        for (path, inside) in [
+            ("", true),
+            ("trips", true),
+            ("trips/2024", true),
+            ("trips/2023", false),
+            ("trip", false),
+            ("2024", false),
+            ("trips/2024/sea.jpg", false),
+        ] {
+            assert_eq!(in_folder(&folders, path), inside, "{}", path);
+        }
+    }
+}
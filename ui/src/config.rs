@@ -0,0 +1,224 @@
+//! Where the UI finds the API, media files and upload endpoint, and how
+//! hard it may push them.
+//!
+//! Settings are layered: values saved on the settings page win over
+//! `config.json`, fetched from next to the UI at startup, which wins over
+//! the values the UI was built with.
+
+use std::time::Duration;
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{RequestPolicy, TransportKind, UploadPolicy},
+    log, path, storage,
+};
+
+const STORAGE_KEY: &str = "media-manager.config";
+
+/// Replace the `from` prefix of media URLs with `to`, e.g. when the API
+/// reports URLs on an internal host name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+impl UrlRewrite {
+    pub fn apply(&self, url: &str) -> String {
+        match url.strip_prefix(&self.from) {
+            Some(rest) if !self.from.is_empty() => format!("{}{}", self.to, rest),
+            _ => url.to_string(),
+        }
+    }
+}
+
+/// One layer of settings. Unset values fall through to the layer below.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Origin of the default server's API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_url_rewrite: Option<UrlRewrite>,
+    /// tus endpoint of the default server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_url: Option<String>,
+    /// How requests and events travel, for every remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_transport: Option<TransportKind>,
+    /// Seconds an API request may take, for every remote. At least 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<u32>,
+    /// Times failed idempotent requests are retried, for every remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    /// Uploads that may run at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_concurrency: Option<u32>,
+    /// Megabytes sent per upload request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_chunk_size: Option<u32>,
+    /// Kilobytes per second all uploads together may send. 0 means no
+    /// limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_bandwidth: Option<u32>,
+}
+
+impl ApiConfig {
+    /// Values the UI was built with
+    fn built_in() -> Self {
+        Self {
+            api_base_url: option_env!("API_BASE_URL").map(str::to_owned),
+            media_url_rewrite: None,
+            upload_url: None,
+            api_transport: option_env!("API_TRANSPORT").and_then(TransportKind::parse),
+            request_timeout: None,
+            max_retries: None,
+            upload_concurrency: None,
+            upload_chunk_size: None,
+            upload_bandwidth: None,
+        }
+    }
+
+    /// `self` with unset values taken from `fallback`
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            api_base_url: self.api_base_url.or(fallback.api_base_url),
+            media_url_rewrite: self.media_url_rewrite.or(fallback.media_url_rewrite),
+            upload_url: self.upload_url.or(fallback.upload_url),
+            api_transport: self.api_transport.or(fallback.api_transport),
+            request_timeout: self.request_timeout.or(fallback.request_timeout),
+            max_retries: self.max_retries.or(fallback.max_retries),
+            upload_concurrency: self.upload_concurrency.or(fallback.upload_concurrency),
+            upload_chunk_size: self.upload_chunk_size.or(fallback.upload_chunk_size),
+            upload_bandwidth: self.upload_bandwidth.or(fallback.upload_bandwidth),
+        }
+    }
+
+    /// Origin of the default server, the one the UI was loaded from
+    /// unless configured otherwise
+    pub fn base_url(&self) -> String {
+        match &self.api_base_url {
+            Some(url) => url.trim_end_matches('/').to_owned(),
+            None => window()
+                .location()
+                .origin()
+                .expect("window.location.origin"),
+        }
+    }
+
+    /// tus endpoint of the default server
+    pub fn upload_url(&self) -> String {
+        match &self.upload_url {
+            Some(url) => url.clone(),
+            None => format!("{}/files", self.base_url()),
+        }
+    }
+
+    pub fn transport(&self) -> TransportKind {
+        self.api_transport.unwrap_or_default()
+    }
+
+    pub fn request_policy(&self) -> RequestPolicy {
+        let default = RequestPolicy::default();
+        RequestPolicy {
+            timeout: self
+                .request_timeout
+                .map_or(default.timeout, |t| Duration::from_secs(t.max(1).into())),
+            retries: self.max_retries.unwrap_or(default.retries),
+        }
+    }
+
+    /// `self` without the upload settings, which uploads follow as they
+    /// run rather than being handed a new client
+    pub fn without_upload_policy(self) -> Self {
+        Self {
+            upload_concurrency: None,
+            upload_chunk_size: None,
+            upload_bandwidth: None,
+            ..self
+        }
+    }
+
+    pub fn upload_policy(&self) -> UploadPolicy {
+        let default = UploadPolicy::default();
+        UploadPolicy {
+            concurrency: self
+                .upload_concurrency
+                .map_or(default.concurrency, |c| c.max(1) as usize),
+            chunk_size: self
+                .upload_chunk_size
+                .map_or(default.chunk_size, |mb| mb.max(1) as f64 * 1_000_000.0),
+            bandwidth: self
+                .upload_bandwidth
+                .filter(|kb| *kb > 0)
+                .map(|kb| kb as f64 * 1000.0),
+        }
+    }
+}
+
+/// Settings in effect, provided to the component tree as context
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Contents of `config.json`
+    file: RwSignal<ApiConfig>,
+    /// Overrides saved on the settings page
+    local: RwSignal<ApiConfig>,
+}
+
+impl Config {
+    /// Load saved overrides, keep them saved and fetch `config.json`
+    pub fn new() -> Self {
+        let config = Self {
+            file: create_rw_signal(ApiConfig::default()),
+            local: create_rw_signal(storage::load(STORAGE_KEY).unwrap_or_default()),
+        };
+        create_effect(move |_| storage::save(STORAGE_KEY, &config.local.get()));
+        spawn_local(async move {
+            match fetch().await {
+                Ok(file) => config.file.set(file),
+                Err(e) => log!("not using config.json: {}", e),
+            }
+        });
+        config
+    }
+
+    /// Effective settings
+    pub fn get(&self) -> ApiConfig {
+        self.local.get().or(self.defaults())
+    }
+
+    /// What the settings would be without local overrides
+    pub fn defaults(&self) -> ApiConfig {
+        self.file.get().or(ApiConfig::built_in())
+    }
+
+    pub fn overrides(&self) -> ApiConfig {
+        self.local.get()
+    }
+
+    pub fn set_overrides(&self, overrides: ApiConfig) {
+        self.local.set(overrides)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn fetch() -> Result<ApiConfig, gloo_net::Error> {
+    let response = gloo_net::http::Request::get(&path("config.json"))
+        .send()
+        .await?;
+    if !response.ok() {
+        return Err(gloo_net::Error::GlooError(format!(
+            "server responded with status {}",
+            response.status()
+        )));
+    }
+    response.json().await
+}
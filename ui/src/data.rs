@@ -1,34 +1,264 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MediaItem {
     pub id: String,
     pub url: String,
     pub title: String,
     pub format: String,
+    /// Duration in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// Frame width in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Frame height in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    /// Overall bitrate in bits per second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    /// File size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// RFC 3339 timestamp of when the file was added to the library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<String>,
+    /// Name of the collection this item belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Soft-deleted items stay in the trash until they are restored or
+    /// deleted permanently
+    #[serde(default)]
+    pub trashed: bool,
 }
 
 impl MediaItem {
-    pub fn kind(&self) -> &'static str {
-        match self.format.as_str() {
-            "mkv" | "mp4" | "ogg" | "webm" => "video",
-            "jpeg" | "jpg" | "png" | "webp" => "image",
-            _ => "unknown",
+    pub fn kind(&self) -> MediaKind {
+        MediaKind::from_format(&self.format)
+    }
+
+    pub fn update(&mut self, field: MediaField) {
+        match field {
+            MediaField::Title(value) => self.title = value,
+            MediaField::Format(value) => self.format = value,
+            MediaField::Collection(value) => {
+                self.collection = Some(value).filter(|v| !v.is_empty())
+            }
+        }
+    }
+
+    /// Current value of the field that `field` refers to
+    pub fn field(&self, field: &MediaField) -> MediaField {
+        match field {
+            MediaField::Title(_) => MediaField::Title(self.title.clone()),
+            MediaField::Format(_) => MediaField::Format(self.format.clone()),
+            MediaField::Collection(_) => {
+                MediaField::Collection(self.collection.clone().unwrap_or_default())
+            }
+        }
+    }
+
+    /// `WIDTHxHEIGHT` if both dimensions are known
+    pub fn resolution(&self) -> Option<String> {
+        Some(format!("{}x{}", self.width?, self.height?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaKind {
+    Video,
+    Image,
+    Audio,
+    Unknown,
+}
+
+impl MediaKind {
+    /// Infer the kind of media from a file extension
+    pub fn from_format(format: &str) -> Self {
+        match format.to_lowercase().as_str() {
+            "mkv" | "mp4" | "ogg" | "webm" | "mov" | "m4v" => Self::Video,
+            "jpeg" | "jpg" | "png" | "webp" | "gif" | "avif" => Self::Image,
+            "mp3" | "flac" | "opus" | "m4a" | "wav" => Self::Audio,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Route segment and display name for this kind
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Image => "image",
+            Self::Audio => "audio",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An editable field of a [`MediaItem`] along with its new value
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaField {
+    Title(String),
+    Format(String),
+    /// An empty collection name removes the item from its collection
+    Collection(String),
+}
+
+impl MediaField {
+    /// Field name as understood by the API
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Title(_) => "title",
+            Self::Format(_) => "format",
+            Self::Collection(_) => "collection",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Title(v) | Self::Format(v) | Self::Collection(v) => v,
         }
     }
 
-    pub fn update(&mut self, field: String, value: String) {
-        match field.as_str() {
-            "title" => self.title = value,
-            "format" => self.format = value,
-            _ => {}
+    /// Check that the new value is acceptable for this field
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Title(v) if v.trim().is_empty() => Err("title must not be empty".to_string()),
+            Self::Format(v) if v.is_empty() => Err("format must not be empty".to_string()),
+            Self::Format(v) if !v.chars().all(|c| c.is_ascii_alphanumeric()) => Err(format!(
+                "format must be a file extension without a leading dot, got {:?}",
+                v
+            )),
+            _ => Ok(()),
         }
     }
 }
 
-#[derive(Clone)]
+/// Change to the library announced by the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MediaEvent {
+    Created { item: MediaItem },
+    Updated {
+        item: MediaItem,
+        /// Set when the change was caused by a [`MediaUpdate`]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+    Removed { id: String },
+    /// Local state can't be patched up from events; reload the library
+    Resync,
+}
+
+/// Number of items requested per page
+pub const PAGE_SIZE: usize = 100;
+
+/// Filters and cursor for fetching one page of the library
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaQuery {
+    /// Case-insensitive substring of the title
+    pub q: String,
+    pub kind: Option<MediaKind>,
+    /// Only items in (or out of) the trash
+    pub trashed: Option<bool>,
+    /// Fetch a single item
+    pub id: Option<String>,
+    /// Opaque cursor taken from [`MediaPage::next`]
+    pub cursor: Option<String>,
+    pub limit: usize,
+}
+
+impl MediaQuery {
+    /// First page of items with `id`
+    pub fn by_id(id: String) -> Self {
+        Self {
+            id: Some(id),
+            limit: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, item: &MediaItem) -> bool {
+        self.id.as_ref().map_or(true, |id| &item.id == id)
+            && item.title.to_lowercase().contains(&self.q.to_lowercase())
+            && self.kind.map_or(true, |kind| item.kind() == kind)
+            && self.trashed.map_or(true, |trashed| item.trashed == trashed)
+    }
+
+    /// Whether both queries ask for the same items, regardless of page
+    pub fn same_filters(&self, other: &Self) -> bool {
+        Self {
+            cursor: None,
+            ..self.clone()
+        } == Self {
+            cursor: None,
+            ..other.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MediaPage {
+    pub items: Vec<MediaItem>,
+    /// Cursor for the next page, if there is one
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// Ways of moving an item in and out of the trash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrashOp {
+    Trash,
+    Restore,
+    /// Remove a trashed item for good
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashRequest {
+    pub id: String,
+    pub op: TrashOp,
+    /// Set when the request is part of a bulk operation
+    pub batch: Option<String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct MediaUpdate {
     pub id: String,
-    pub field: String,
-    pub value: String,
+    pub field: MediaField,
+    /// Set when the update is part of a bulk operation
+    pub batch: Option<String>,
+    /// Generated by the client so that the server's acknowledgement can
+    /// be matched to the edit. Retries reuse the id.
+    pub request_id: String,
+}
+
+impl MediaUpdate {
+    pub fn new(id: String, field: MediaField, batch: Option<String>) -> Self {
+        Self {
+            id,
+            field,
+            batch,
+            request_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Whether both updates edit the same field of the same item
+    pub fn same_field(&self, other: &Self) -> bool {
+        self.id == other.id && self.field.name() == other.field.name()
+    }
 }
@@ -7,15 +7,31 @@ use leptos_meta::*;
 use leptos_router::*;
 
 // Modules
+mod auth;
 mod client;
 mod components;
+mod config;
 mod data;
 mod pages;
+mod remotes;
+mod storage;
 
-use data::{MediaItem, MediaUpdate};
+use data::{
+    MediaEvent, MediaField, MediaItem, MediaKind, MediaPage, MediaQuery, MediaUpdate, TrashOp,
+    TrashRequest, PAGE_SIZE,
+};
 
-use components::dashboard::{Editor, Selector};
-use components::notification_tray::NotificationTray;
+use auth::Auth;
+use client::ConnectionState;
+use config::Config;
+use remotes::{Remotes, Source};
+use components::auth::LoginRedirect;
+use components::dashboard::{player, Editor, Selector};
+use components::notification_tray::{NotificationTray, Notifier};
+use components::remotes::RemoteSwitcher;
+use components::selection::Batches;
+use components::uploads::{UploadManager, Uploads};
+use components::ErrorMessage;
 
 #[macro_export]
 macro_rules! log {
@@ -43,55 +59,406 @@ pub(crate) fn path(p: &str) -> String {
     }
 }
 
+/// Dashboard route for items of `kind`. Items without a dedicated
+/// dashboard live in the "all" route.
+fn route_for(kind: MediaKind) -> &'static str {
+    match kind {
+        MediaKind::Unknown => "all",
+        kind => kind.as_str(),
+    }
+}
+
+/// Fired when the library has to be reloaded from scratch
+#[derive(Clone, Copy)]
+pub(crate) struct Resync(Trigger);
+
+impl Resync {
+    pub fn track(&self) {
+        self.0.track()
+    }
+
+    pub fn notify(&self) {
+        self.0.notify()
+    }
+}
+
+/// Number of times the client has been replaced. Pages are tagged with
+/// it so that those requested from an earlier client can be told apart,
+/// even when both clients read from the same source.
+#[derive(Clone, Copy)]
+pub(crate) struct ClientGeneration(RwSignal<usize>);
+
+impl ClientGeneration {
+    pub fn get_untracked(&self) -> usize {
+        self.0.get_untracked()
+    }
+
+    fn bump(&self) {
+        self.0.update(|g| *g += 1)
+    }
+}
+
+/// A query along with the page it returned, tagged with the generation
+/// of the client it was loaded with
+pub(crate) type LoadResult = (usize, MediaQuery, client::Result<MediaPage>);
+
+/// Outcome of an edit. Failed edits carry the update so they can be retried.
+pub(crate) type UpdateResult = Result<MediaUpdate, (MediaUpdate, client::ClientError)>;
+
+/// Restore `previous` unless the field has been changed again since `update`
+fn rollback(
+    set_media: WriteSignal<HashMap<String, MediaItem>>,
+    update: &MediaUpdate,
+    previous: MediaField,
+) {
+    set_media.update(|m| {
+        if let Some(item) = m.get_mut(&update.id) {
+            if item.field(&update.field) == update.field {
+                item.update(previous)
+            }
+        }
+    })
+}
+
+/// Where an edit stands with the server
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EditState {
+    /// Applied locally, waiting for the server to accept it
+    Pending,
+    Failed(client::ClientError),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PendingUpdate {
+    pub update: MediaUpdate,
+    pub state: EditState,
+}
+
+/// Edits the server hasn't acknowledged yet and edits it rejected, in the
+/// order they were made
+#[derive(Clone, Copy)]
+pub(crate) struct PendingUpdates(RwSignal<Vec<PendingUpdate>>);
+
+impl PendingUpdates {
+    pub fn new() -> Self {
+        Self(create_rw_signal(Vec::new()))
+    }
+
+    /// Track `update` as pending. Earlier failures to edit the same field
+    /// are superseded by it.
+    fn start(&self, update: &MediaUpdate) {
+        self.0.update(|p| {
+            p.retain(|e| {
+                e.update.request_id != update.request_id
+                    && !(e.update.same_field(update) && e.state != EditState::Pending)
+            });
+            p.push(PendingUpdate {
+                update: update.clone(),
+                state: EditState::Pending,
+            })
+        })
+    }
+
+    /// The server accepted the edit with `request_id`
+    fn ack(&self, request_id: &str) {
+        self.0.update(|p| p.retain(|e| e.update.request_id != request_id))
+    }
+
+    /// The server rejected `update`
+    fn fail(&self, update: &MediaUpdate, error: client::ClientError) {
+        self.0.update(|p| {
+            p.retain(|e| e.update.request_id != update.request_id);
+            p.push(PendingUpdate {
+                update: update.clone(),
+                state: EditState::Failed(error),
+            })
+        })
+    }
+
+    /// Latest edit to `field` of item `id` that the server hasn't accepted
+    pub fn latest(&self, id: &str, field: &str) -> Option<PendingUpdate> {
+        self.0.with(|p| {
+            p.iter()
+                .rev()
+                .find(|e| e.update.id == id && e.update.field.name() == field)
+                .cloned()
+        })
+    }
+
+    /// Apply edits that are still in flight on top of `item`, which came
+    /// from the server and may not include them yet
+    fn reapply(&self, item: &mut MediaItem) {
+        self.0.with_untracked(|p| {
+            for e in p {
+                if e.update.id == item.id && e.state == EditState::Pending {
+                    item.update(e.update.field.clone())
+                }
+            }
+        })
+    }
+}
+
+impl Default for PendingUpdates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outcome of moving an item in or out of the trash
+pub(crate) type TrashResult = (TrashRequest, client::Result<()>);
+
 #[component]
 pub fn App() -> impl IntoView {
     let (media, set_media) = create_signal(HashMap::<String, MediaItem>::new());
-    let get_media_action = create_action(|_: &()| async move { client::get_media().await });
+    let config = Config::new();
+    // changing how uploads run doesn't call for new clients
+    let settings = create_memo(move |_| config.get().without_upload_policy());
+    let upload_policy = create_memo(move |_| config.get().upload_policy());
+    let remotes = Remotes::new();
+    let auth = Auth::new();
+    let selection = create_memo(move |_| remotes.selection());
+    let (message, set_message) = create_signal(None::<View>);
+    let notifier = Notifier::new(set_message);
+    let hooks = client::Hooks {
+        on_unauthorized: Callback::new(move |remote| auth.require(remote)),
+        on_retry: Callback::new(move |retry: client::Retry| {
+            notifier.notify(format!(
+                "{} failed: {}. Retrying in {:.0}s (attempt {} of {})",
+                retry.request,
+                retry.error,
+                retry.delay.as_secs_f64(),
+                retry.attempt,
+                retry.attempts
+            ))
+        }),
+    };
+    let client_for = move || {
+        selection.with(|(source, r)| settings.with(|c| client::client_for(source, r, c, hooks)))
+    };
+    let client = create_rw_signal(untrack(client_for));
+    let generation = ClientGeneration(create_rw_signal(0));
+    create_effect(move |first: Option<()>| {
+        let c = client_for();
+        if first.is_some() {
+            generation.bump();
+            client.set(c)
+        }
+    });
+    // every source keeps its own store, so switching back to one shows
+    // what was already loaded while it is refreshed
+    let stores = store_value(HashMap::<String, HashMap<String, MediaItem>>::new());
+    let is_demo = move || client.with(|c| c.is_demo());
+    let batches = Batches::new();
+    let uploads = Uploads::new(
+        client,
+        remotes,
+        Callback::new(move |remote: remotes::Remote| {
+            let source = Source::Remote(remote.id.clone());
+            settings.with_untracked(|c| client::client_for(&source, &[remote], c, hooks))
+        }),
+        upload_policy.into(),
+        notifier,
+    );
+    let resync = Resync(create_trigger());
+    let connection = create_rw_signal(ConnectionState::Reconnecting);
+    let pending = PendingUpdates::new();
+    let source_key = move || selection.with_untracked(|(source, _)| source.key());
+    let get_media_action = create_action(move |query: &MediaQuery| {
+        let query = query.clone();
+        let client = client.get_untracked();
+        let loaded_with = generation.get_untracked();
+        async move { (loaded_with, query.clone(), client.get_media(query).await) }
+    });
     create_effect({
         let val = get_media_action.value();
-        move |_| {
-            if let Some(items) = val.get() {
-                for item in items {
-                    set_media.update(|m| {
-                        m.insert(item.id.clone(), item);
-                    })
+        move |_| match val.get() {
+            // pages requested before the client was replaced may come from
+            // another source or server
+            Some((loaded_with, _, _)) if loaded_with != generation.get_untracked() => {}
+            Some((_, _, Ok(page))) => set_media.update(|m| {
+                for item in page.items {
+                    m.insert(item.id.clone(), item);
                 }
-            }
+            }),
+            Some((_, query, Err(e))) => notifier.notify(view! {
+                "Failed to load media"
+                <ErrorMessage error=e retry=move || get_media_action.dispatch(query.clone())/>
+            }),
+            None => {}
         }
     });
-    get_media_action.dispatch(());
-    let update_item_action = create_action(|update: &MediaUpdate| {
+    // start over whenever the client is swapped or the server asks for it
+    create_effect(move |previous: Option<String>| {
+        client.track();
+        resync.track();
+        let key = source_key();
+        let cached = match previous {
+            Some(previous) if previous != key => {
+                let items = media.get_untracked();
+                stores
+                    .try_update_value(|s| {
+                        s.insert(previous, items);
+                        s.remove(&key)
+                    })
+                    .flatten()
+            }
+            _ => None,
+        };
+        set_media.set(cached.unwrap_or_default());
+        get_media_action.dispatch(MediaQuery {
+            limit: PAGE_SIZE,
+            ..Default::default()
+        });
+        key
+    });
+    // Edits are applied to the store right away and rolled back if the
+    // server doesn't accept them. They are tracked in `pending` until the
+    // server acknowledges them, either by responding or by announcing the
+    // change.
+    let update_item_action = create_action(move |update: &MediaUpdate| {
         let u = update.clone();
+        let client = client.get_untracked();
+        let invalid = u.field.validate().err();
+        let previous = match invalid {
+            None => media.with_untracked(|m| m.get(&u.id).map(|item| item.field(&u.field))),
+            Some(_) => None,
+        };
+        if previous.is_some() {
+            pending.start(&u);
+            set_media.update(|m| {
+                if let Some(item) = m.get_mut(&u.id) {
+                    item.update(u.field.clone())
+                }
+            });
+        }
         async move {
-            match client::update_media(u.id.clone(), u.field.clone(), u.value.clone()).await {
-                Ok(true) => Some(u),
-                _ => None,
+            if let Some(e) = invalid {
+                let e = client::ClientError::Invalid(e);
+                pending.fail(&u, e.clone());
+                return Err((u, e));
+            }
+            let result = client
+                .update_media(u.id.clone(), u.field.clone(), u.request_id.clone())
+                .await;
+            match result {
+                Ok(()) => {
+                    pending.ack(&u.request_id);
+                    Ok(u)
+                }
+                Err(e) => {
+                    if let Some(previous) = previous {
+                        rollback(set_media, &u, previous);
+                    }
+                    pending.fail(&u, e.clone());
+                    Err((u, e))
+                }
             }
         }
     });
     create_effect({
         let val = update_item_action.value();
+        move |_| match val.get() {
+            Some(Ok(MediaUpdate { batch: Some(batch), .. })) => {
+                batches.record(&batch, Ok(()), notifier)
+            }
+            Some(Err((MediaUpdate { batch: Some(batch), .. }, e))) => {
+                batches.record(&batch, Err(e), notifier)
+            }
+            Some(Err((u, e))) => notifier.notify(view! {
+                {format!("Failed to set {}", u.field.name())}
+                <ErrorMessage error=e retry=move || update_item_action.dispatch(u.clone())/>
+            }),
+            _ => {}
+        }
+    });
+    let trash_action = create_action(move |req: &TrashRequest| {
+        let req = req.clone();
+        let client = client.get_untracked();
+        async move {
+            let result = match req.op {
+                TrashOp::Trash => client.trash_media(req.id.clone()).await,
+                TrashOp::Restore => client.restore_media(req.id.clone()).await,
+                TrashOp::Delete => client.delete_media(req.id.clone()).await,
+            };
+            (req, result)
+        }
+    });
+    create_effect({
+        let val = trash_action.value();
         move |_| {
-            if let Some(u) = val.get().flatten() {
-                set_media.update(|m| {
-                    if let Some(item) = m.get_mut(&u.id) {
-                        item.update(u.field, u.value)
+            let Some((req, result)) = val.get() else {
+                return;
+            };
+            if result.is_ok() {
+                set_media.update(|m| match req.op {
+                    TrashOp::Trash | TrashOp::Restore => {
+                        if let Some(item) = m.get_mut(&req.id) {
+                            item.trashed = req.op == TrashOp::Trash
+                        }
+                    }
+                    TrashOp::Delete => {
+                        m.remove(&req.id);
                     }
                 })
             }
+            match (&req.batch, result) {
+                (Some(batch), result) => batches.record(batch, result, notifier),
+                (None, Ok(())) => {}
+                (None, Err(e)) => notifier.notify(view! {
+                    {match req.op {
+                        TrashOp::Trash => "Failed to move item to the trash",
+                        TrashOp::Restore => "Failed to restore item",
+                        TrashOp::Delete => "Failed to delete item",
+                    }}
+                    <ErrorMessage error=e retry=move || trash_action.dispatch(req.clone())/>
+                }),
+            }
         }
     });
-    let new_media_source = client::new_media();
-    let (new_media, set_new_media) = create_signal(None::<(String, MediaItem)>);
     create_effect(move |_| {
-        if let Some(item) = new_media_source.get() {
-            let id = item.id.clone();
-            set_media.update(|m| {
-                m.insert(id.clone(), item.clone());
-            });
-            set_new_media.set(Some((id, item)))
-        }
+        // the source is disposed along with this effect when the client changes
+        let events = client.get().media_events();
+        create_effect(move |_| connection.set(events.state.get()));
+        create_effect(move |_| match events.events.get() {
+            Some(MediaEvent::Created { item }) => {
+                let id = item.id.clone();
+                set_media.update(|m| {
+                    m.insert(id.clone(), item.clone());
+                });
+                notifier.notify(view! {
+                    <a href=path(
+                        &format!("{}/{}", route_for(item.kind()), id),
+                    )>"New Media! " {item.title}</a>
+                })
+            }
+            Some(MediaEvent::Updated { mut item, request_id }) => {
+                if let Some(request_id) = request_id {
+                    pending.ack(&request_id)
+                }
+                pending.reapply(&mut item);
+                set_media.update(|m| {
+                    m.insert(item.id.clone(), item);
+                })
+            }
+            Some(MediaEvent::Removed { id }) => set_media.update(|m| {
+                m.remove(&id);
+            }),
+            Some(MediaEvent::Resync) => resync.notify(),
+            None => {}
+        });
     });
+    provide_context(trash_action);
+    provide_context(batches);
+    provide_context(uploads);
+    provide_context(resync);
+    provide_context(remotes);
+    provide_context(config);
+    provide_context(auth);
+    provide_context(pending);
+    provide_context(notifier);
+    provide_context(get_media_action);
+    provide_context(client);
+    provide_context(generation);
     provide_context(update_item_action);
     provide_context(media);
     provide_meta_context();
@@ -101,19 +468,42 @@ pub fn App() -> impl IntoView {
         <Meta charset="UTF-8"/>
         <Meta name="viewport" content="width=device-width, initial-scale=1.0"/>
         <Router>
+            <LoginRedirect/>
             <div id="nav-container">
                 <nav>
                     <ul>
                         <li>
                             <a href=path("")>"Home"</a>
                         </li>
+                        <li>
+                            <a href=path("all")>"All Media"</a>
+                        </li>
                         <li>
                             <a href=path("video")>"Videos"</a>
                         </li>
                         <li>
                             <a href=path("image")>"Images"</a>
                         </li>
+                        <li>
+                            <a href=path("audio")>"Audio"</a>
+                        </li>
+                        <li>
+                            <a href=path("trash")>"Trash"</a>
+                        </li>
+                        <li>
+                            <a href=path("remotes")>"Remotes"</a>
+                        </li>
+                        <li>
+                            <a href=path("settings")>"Settings"</a>
+                        </li>
                     </ul>
+                    <RemoteSwitcher/>
+                    <span
+                        class=move || format!("connection-status {}", connection.get().as_str())
+                        title="Live updates"
+                    >
+                        {move || connection.get().as_str()}
+                    </span>
                 </nav>
             </div>
             <main>
@@ -121,27 +511,65 @@ pub fn App() -> impl IntoView {
                     <div id="heading-ctr">
                         <h1>"Media Manager"</h1>
 
-                        {
-                            #[cfg(feature = "demo")]
-                            view! { <h2>"Demo Mode"</h2> }
-                        }
+                        <Show when=is_demo>
+                            <h2>"Demo Mode"</h2>
+                        </Show>
+                        <label class="demo-toggle">
+                            <input
+                                type="checkbox"
+                                prop:checked=is_demo
+                                on:change=move |e| {
+                                    remotes
+                                        .set_active(
+                                            if event_target_checked(&e) {
+                                                Source::Demo
+                                            } else {
+                                                Source::Remote(remotes::DEFAULT_REMOTE.to_string())
+                                            },
+                                        )
+                                }
+                            />
+
+                            "Use demo data"
+                        </label>
 
                     </div>
                 </div>
-                <NotificationTray message=move || {
-                    new_media
-                        .get()
-                        .map(|(id, item)| {
-                            view! {
-                                <a href=path(
-                                    &format!("{}/{}", item.kind(), id),
-                                )>"New Media! " {item.title}</a>
-                            }
-                                .into_view()
-                        })
-                }/>
+                <NotificationTray message=message/>
+                <UploadManager/>
                 <Routes base=option_env!("APP_BASE_PATH").unwrap_or_default().to_owned()>
                     <Route path="/" view=pages::Home/>
+                    <Route path="remotes" view=pages::ManageRemotes/>
+                    <Route path="settings" view=pages::Settings/>
+                    <Route path="login" view=pages::Login/>
+                    <Route
+                        path="all"
+                        view=|| {
+                            view! {
+                                <div class="dashboard">
+                                    <Selector
+                                        path="all".to_string()
+                                        show_kind=true
+                                        filter=|search, item| {
+                                            item.title.to_lowercase().contains(&search.to_lowercase())
+                                        }
+                                    />
+
+                                    <Outlet/>
+                                </div>
+                            }
+                        }
+                    >
+
+                        <Route path="" view=|| view! {}/>
+                        <Route
+                            path=":id"
+                            view=move || {
+                                view! { <Editor render=player/> }
+                            }
+                        />
+
+                    </Route>
                     <Route
                         path="video"
                         view=|| {
@@ -149,9 +577,10 @@ pub fn App() -> impl IntoView {
                                 <div class="dashboard">
                                     <Selector
                                         path="video".to_string()
+                                        kind=MediaKind::Video
                                         filter=|search, item| {
                                             item.title.to_lowercase().contains(&search.to_lowercase())
-                                                && item.kind() == "video"
+                                                && item.kind() == MediaKind::Video
                                         }
                                     />
 
@@ -166,7 +595,7 @@ pub fn App() -> impl IntoView {
                             path=":id"
                             view=move || {
                                 view! {
-                                    <Editor render=|url| {
+                                    <Editor render=|url, _| {
                                         view! {
                                             <video controls>
                                                 <source src=url/>
@@ -185,9 +614,10 @@ pub fn App() -> impl IntoView {
                                 <div class="dashboard">
                                     <Selector
                                         path="image".to_string()
+                                        kind=MediaKind::Image
                                         filter=|search, item| {
                                             item.title.to_lowercase().contains(&search.to_lowercase())
-                                                && item.kind() == "image"
+                                                && item.kind() == MediaKind::Image
                                         }
                                     />
 
@@ -202,7 +632,7 @@ pub fn App() -> impl IntoView {
                             path=":id"
                             view=move || {
                                 view! {
-                                    <Editor render=|url| {
+                                    <Editor render=|url, _| {
                                         view! { <img src=url/> }
                                     }/>
                                 }
@@ -210,6 +640,44 @@ pub fn App() -> impl IntoView {
                         />
 
                     </Route>
+                    <Route
+                        path="audio"
+                        view=|| {
+                            view! {
+                                <div class="dashboard">
+                                    <Selector
+                                        path="audio".to_string()
+                                        kind=MediaKind::Audio
+                                        filter=|search, item| {
+                                            item.title.to_lowercase().contains(&search.to_lowercase())
+                                                && item.kind() == MediaKind::Audio
+                                        }
+                                    />
+
+                                    <Outlet/>
+                                </div>
+                            }
+                        }
+                    >
+
+                        <Route path="" view=|| view! {}/>
+                        <Route
+                            path=":id"
+                            view=move || {
+                                view! {
+                                    <Editor render=|url, _| {
+                                        view! {
+                                            <audio controls>
+                                                <source src=url/>
+                                            </audio>
+                                        }
+                                    }/>
+                                }
+                            }
+                        />
+
+                    </Route>
+                    <Route path="trash" view=pages::Trash/>
                     <Route path="/*" view=pages::NotFound/>
                 </Routes>
             </main>
@@ -1,7 +1,15 @@
 pub mod home;
+pub mod login;
 // pub mod images;
 pub mod not_found;
+pub mod remotes;
+pub mod settings;
+pub mod trash;
 // pub mod videos;
 
 pub use home::Home;
+pub use login::Login;
 pub use not_found::NotFound;
+pub use remotes::ManageRemotes;
+pub use settings::Settings;
+pub use trash::Trash;
@@ -1,8 +1,12 @@
 use leptos::*;
 
+use crate::client::Client;
+
 /// Default Home Page
 #[component]
 pub fn Home() -> impl IntoView {
+    let client = use_context::<RwSignal<Client>>().unwrap();
+    let github_repo = "https://github.com/littlebenlittle/media-manager";
     view! {
         <div id="media-manager-description" class="content">
             <h2>"Welcome to Media Manager!"</h2>
@@ -17,22 +21,18 @@ pub fn Home() -> impl IntoView {
                  dashboards you can use to interact with your media libarary."
             </p>
 
-            {
-                let github_repo = "https://github.com/littlebenlittle/media-manager";
-                #[cfg(feature = "demo")]
-                view! {
-                    <h3>"Demo Mode"</h3>
-                    <p>
-                        "You are viewing the app in demo mode. This means that there \
-                         isn't an API server and all requests to the API return fake \
-                         responses. If you want to experiment with the full version,  \
-                         you can clone the source code from the "
-                        <a href=github_repo target="_blank">
-                            "GitHub repo"
-                        </a> " and follow the instructions in the README."
-                    </p>
-                }
-            }
+            <Show when=move || client.with(|c| c.is_demo())>
+                <h3>"Demo Mode"</h3>
+                <p>
+                    "You are viewing the app in demo mode. This means that \
+                     all requests to the API return fake responses. If you want \
+                     to experiment with the full version, you can clone the \
+                     source code from the "
+                    <a href=github_repo target="_blank">
+                        "GitHub repo"
+                    </a> " and follow the instructions in the README."
+                </p>
+            </Show>
 
         </div>
     }
@@ -0,0 +1,97 @@
+use leptos::*;
+use leptos_router::{use_navigate, use_query_map, NavigateOptions};
+
+use crate::{
+    auth::Auth,
+    client,
+    components::ErrorMessage,
+    config::Config,
+    path,
+    remotes::{Remotes, DEFAULT_REMOTE},
+};
+
+/// Log in to the remote named by the `remote` query parameter, then go to
+/// `next`
+#[component]
+pub fn Login() -> impl IntoView {
+    let auth = use_context::<Auth>().unwrap();
+    let config = use_context::<Config>().unwrap();
+    let remotes = use_context::<Remotes>().unwrap();
+    let query = use_query_map();
+    let remote_id = move || {
+        query.with(|q| q.get("remote").cloned()).unwrap_or(DEFAULT_REMOTE.to_string())
+    };
+    let remote = move || {
+        let id = remote_id();
+        remotes.list().into_iter().find(|r| r.id == id)
+    };
+    let username = create_rw_signal(String::new());
+    let password = create_rw_signal(String::new());
+    let login = create_action(move |(): &()| {
+        let (remote, settings) = untrack(|| (remote(), config.get()));
+        let (username, password) = (username.get_untracked(), password.get_untracked());
+        async move {
+            let Some(remote) = remote else {
+                return Err(client::ClientError::Invalid("unknown remote".to_string()));
+            };
+            let token = client::login(&remote.origin(&settings), &username, &password).await?;
+            Ok((remote, token))
+        }
+    });
+    let navigate = use_navigate();
+    create_effect(move |_| {
+        let Some(Ok((mut remote, token))) = login.value().get() else {
+            return;
+        };
+        let id = remote.id.clone();
+        remote.token = Some(token);
+        remotes.update(remote);
+        auth.done(&id);
+        password.set(String::new());
+        let next = query
+            .with_untracked(|q| q.get("next").cloned())
+            .unwrap_or_else(|| path(""));
+        navigate(&next, NavigateOptions::default())
+    });
+    let submit = move |e: ev::SubmitEvent| {
+        e.prevent_default();
+        login.dispatch(())
+    };
+    view! {
+        <div id="login" class="content">
+            <h2>
+                "Log in to "
+                {move || remote().map(|r| r.name).unwrap_or_else(remote_id)}
+            </h2>
+            <form on:submit=submit>
+                <input
+                    type="text"
+                    placeholder="username"
+                    autocomplete="username"
+                    prop:value=username
+                    on:input=move |e| username.set(event_target_value(&e))
+                />
+                <input
+                    type="password"
+                    placeholder="password"
+                    autocomplete="current-password"
+                    prop:value=password
+                    on:input=move |e| password.set(event_target_value(&e))
+                />
+                <button type="submit" disabled=login.pending()>
+                    "Log in"
+                </button>
+            </form>
+            {move || match login.value().get() {
+                Some(Err(client::ClientError::Status(401, _))) => {
+                    view! { <p class="error-message">"Wrong username or password"</p> }
+                        .into_view()
+                }
+                Some(Err(e)) => {
+                    view! { <ErrorMessage error=e retry=move || login.dispatch(())/> }.into_view()
+                }
+                _ => view! {}.into_view(),
+            }}
+        </div>
+    }
+}
@@ -0,0 +1,153 @@
+use leptos::*;
+use leptos_router::{use_navigate, NavigateOptions};
+
+use crate::{
+    auth::login_path,
+    components::ConfirmDialog,
+    path,
+    remotes::{Remote, Remotes, Source, DEFAULT_REMOTE},
+};
+
+/// Add, edit and forget the servers the UI can browse
+#[component]
+pub fn ManageRemotes() -> impl IntoView {
+    let remotes = use_context::<Remotes>().unwrap();
+    let name = create_rw_signal(String::new());
+    let base_url = create_rw_signal(String::new());
+    let token = create_rw_signal(String::new());
+    let add = move |e: ev::SubmitEvent| {
+        e.prevent_default();
+        let (n, url, t) = (
+            name.get_untracked(),
+            base_url.get_untracked(),
+            token.get_untracked(),
+        );
+        if n.trim().is_empty() || url.trim().is_empty() {
+            return;
+        }
+        remotes.add(n, url, Some(t).filter(|t| !t.is_empty()));
+        name.set(String::new());
+        base_url.set(String::new());
+        token.set(String::new());
+    };
+    view! {
+        <div id="remotes" class="content">
+            <h2>"Remotes"</h2>
+            <table>
+                <tr>
+                    <th>"name"</th>
+                    <th>"URL"</th>
+                    <th>"token"</th>
+                    <th></th>
+                </tr>
+                <For
+                    each=move || remotes.list()
+                    key=|remote| remote.id.clone()
+                    children=move |remote| view! { <RemoteRow remote=remote/> }
+                />
+            </table>
+            <form class="add-remote" on:submit=add>
+                <h3>"Add a remote"</h3>
+                <input
+                    type="text"
+                    placeholder="name"
+                    prop:value=name
+                    on:input=move |e| name.set(event_target_value(&e))
+                />
+                <input
+                    type="url"
+                    placeholder="https://example.com"
+                    prop:value=base_url
+                    on:input=move |e| base_url.set(event_target_value(&e))
+                />
+                <input
+                    type="password"
+                    placeholder="token (optional)"
+                    prop:value=token
+                    on:input=move |e| token.set(event_target_value(&e))
+                />
+                <button type="submit">"Add"</button>
+            </form>
+        </div>
+    }
+}
+
+#[component]
+fn RemoteRow(remote: Remote) -> impl IntoView {
+    let remotes = use_context::<Remotes>().unwrap();
+    let confirm_remove = create_rw_signal(false);
+    let navigate = use_navigate();
+    let edit = {
+        let remote = remote.clone();
+        move |change: &dyn Fn(&mut Remote)| {
+            let mut remote = remote.clone();
+            change(&mut remote);
+            remotes.update(remote)
+        }
+    };
+    let (set_name, set_url, set_token) = (edit.clone(), edit.clone(), edit);
+    let id = remote.id.clone();
+    let active = {
+        let id = id.clone();
+        move || remotes.active() == Source::Remote(id.clone())
+    };
+    view! {
+        <tr class:active=active.clone()>
+            <td>
+                <input
+                    type="text"
+                    value=remote.name.clone()
+                    on:change=move |e| {
+                        let value = event_target_value(&e);
+                        set_name(&move |r| r.name = value.clone())
+                    }
+                />
+            </td>
+            <td>
+                <input
+                    type="url"
+                    value=remote.base_url.clone()
+                    placeholder=(remote.id == DEFAULT_REMOTE).then_some("from settings")
+                    on:change=move |e| {
+                        let value = event_target_value(&e);
+                        set_url(&move |r| r.base_url = value.trim_end_matches('/').to_string())
+                    }
+                />
+            </td>
+            <td>
+                <input
+                    type="password"
+                    value=remote.token.clone().unwrap_or_default()
+                    on:change=move |e| {
+                        let value = event_target_value(&e);
+                        set_token(&move |r| r.token = Some(value.clone()).filter(|t| !t.is_empty()))
+                    }
+                />
+            </td>
+            <td>
+                <button
+                    disabled=active
+                    on:click={
+                        let id = id.clone();
+                        move |_| remotes.set_active(Source::Remote(id.clone()))
+                    }
+                >
+                    "Browse"
+                </button>
+                <button on:click={
+                    let login = login_path(&id, &path("remotes"));
+                    move |_| navigate(&login, NavigateOptions::default())
+                }>"Log in"</button>
+                <button class="danger" on:click=move |_| confirm_remove.set(true)>
+                    "Forget"
+                </button>
+                <ConfirmDialog
+                    show=confirm_remove
+                    message=format!("Forget {}?", remote.name)
+                    confirm="Forget".to_string()
+                    on_confirm=move || remotes.remove(&id)
+                />
+            </td>
+        </tr>
+    }
+}
@@ -0,0 +1,212 @@
+use leptos::*;
+
+use crate::{
+    client::TransportKind,
+    config::{ApiConfig, Config, UrlRewrite},
+};
+
+/// Point the UI at another backend and tune requests and uploads without
+/// rebuilding it. Empty fields fall back to `config.json` and then to the
+/// values the UI was built with.
+#[component]
+pub fn Settings() -> impl IntoView {
+    let config = use_context::<Config>().unwrap();
+    let overrides = config.overrides();
+    let field = |value: Option<String>| create_rw_signal(value.unwrap_or_default());
+    let api_base_url = field(overrides.api_base_url);
+    let upload_url = field(overrides.upload_url);
+    let rewrite_from = field(overrides.media_url_rewrite.as_ref().map(|r| r.from.clone()));
+    let rewrite_to = field(overrides.media_url_rewrite.map(|r| r.to));
+    let request_timeout = field(overrides.request_timeout.map(|t| t.to_string()));
+    let max_retries = field(overrides.max_retries.map(|r| r.to_string()));
+    let upload_concurrency = field(overrides.upload_concurrency.map(|c| c.to_string()));
+    let upload_chunk_size = field(overrides.upload_chunk_size.map(|c| c.to_string()));
+    let upload_bandwidth = field(overrides.upload_bandwidth.map(|b| b.to_string()));
+    let api_transport = create_rw_signal(overrides.api_transport);
+    let defaults = create_memo(move |_| config.defaults());
+    let placeholder =
+        move |value: fn(&ApiConfig) -> String| Signal::derive(move || defaults.with(value));
+    let set = |value: String| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+    let save = move |e: ev::SubmitEvent| {
+        e.prevent_default();
+        config.set_overrides(ApiConfig {
+            api_base_url: set(api_base_url.get_untracked())
+                .map(|url| url.trim_end_matches('/').to_string()),
+            media_url_rewrite: set(rewrite_from.get_untracked()).map(|from| UrlRewrite {
+                from,
+                to: rewrite_to.get_untracked().trim().to_string(),
+            }),
+            upload_url: set(upload_url.get_untracked()),
+            api_transport: api_transport.get_untracked(),
+            request_timeout: set(request_timeout.get_untracked()).and_then(|t| t.parse().ok()),
+            max_retries: set(max_retries.get_untracked()).and_then(|r| r.parse().ok()),
+            upload_concurrency: set(upload_concurrency.get_untracked())
+                .and_then(|c| c.parse().ok()),
+            upload_chunk_size: set(upload_chunk_size.get_untracked()).and_then(|c| c.parse().ok()),
+            upload_bandwidth: set(upload_bandwidth.get_untracked()).and_then(|b| b.parse().ok()),
+        })
+    };
+    let reset = move |_| {
+        for value in [
+            api_base_url,
+            upload_url,
+            rewrite_from,
+            rewrite_to,
+            request_timeout,
+            max_retries,
+            upload_concurrency,
+            upload_chunk_size,
+            upload_bandwidth,
+        ] {
+            value.set(String::new())
+        }
+        api_transport.set(None);
+        config.set_overrides(ApiConfig::default())
+    };
+    let input = move |value: RwSignal<String>, placeholder: Signal<String>| {
+        view! {
+            <input
+                type="text"
+                placeholder=placeholder
+                prop:value=value
+                on:input=move |e| value.set(event_target_value(&e))
+            />
+        }
+    };
+    let number = move |value: RwSignal<String>, min: u32, placeholder: Signal<String>| {
+        view! {
+            <input
+                type="number"
+                min=min
+                placeholder=placeholder
+                prop:value=value
+                on:input=move |e| value.set(event_target_value(&e))
+            />
+        }
+    };
+    view! {
+        <div id="settings" class="content">
+            <h2>"Settings"</h2>
+            <form on:submit=save>
+                <label>
+                    "API base URL" {input(api_base_url, placeholder(ApiConfig::base_url))}
+                </label>
+                <label>
+                    "Upload endpoint" {input(upload_url, placeholder(ApiConfig::upload_url))}
+                </label>
+                <fieldset>
+                    <legend>"Rewrite media URLs"</legend>
+                    <label>"from" {input(rewrite_from, placeholder(rewrite_from_of))}</label>
+                    <label>"to" {input(rewrite_to, placeholder(rewrite_to_of))}</label>
+                </fieldset>
+                <fieldset>
+                    <legend>"Requests"</legend>
+                    <label>
+                        "transport"
+                        <select on:change=move |e| {
+                            api_transport.set(TransportKind::parse(&event_target_value(&e)))
+                        }>
+                            <option value="" selected=move || api_transport.get().is_none()>
+                                {move || {
+                                    format!(
+                                        "default ({})",
+                                        transport_name(defaults.with(ApiConfig::transport)),
+                                    )
+                                }}
+                            </option>
+                            {[TransportKind::Rest, TransportKind::Ws]
+                                .into_iter()
+                                .map(|kind| {
+                                    view! {
+                                        <option
+                                            value=kind.as_str()
+                                            selected=move || api_transport.get() == Some(kind)
+                                        >
+                                            {transport_name(kind)}
+                                        </option>
+                                    }
+                                })
+                                .collect_view()}
+                        </select>
+                    </label>
+                    <label>
+                        "timeout in seconds"
+                        {number(request_timeout, 1, placeholder(timeout_of))}
+                    </label>
+                    <label>
+                        "retries of failed loads and deletes"
+                        {number(max_retries, 0, placeholder(retries_of))}
+                    </label>
+                </fieldset>
+                <fieldset>
+                    <legend>"Uploads"</legend>
+                    <label>
+                        "uploads at once"
+                        {number(upload_concurrency, 1, placeholder(concurrency_of))}
+                    </label>
+                    <label>
+                        "chunk size in MB"
+                        {number(upload_chunk_size, 1, placeholder(chunk_size_of))}
+                    </label>
+                    <label>
+                        "bandwidth limit in KB/s, 0 for none"
+                        {number(upload_bandwidth, 0, placeholder(bandwidth_of))}
+                    </label>
+                </fieldset>
+                <p class="hint">
+                    "Empty fields use config.json or the defaults the UI was built with."
+                </p>
+                <button type="submit">"Save"</button>
+                <button type="button" on:click=reset>
+                    "Reset"
+                </button>
+            </form>
+        </div>
+    }
+}
+
+fn rewrite_from_of(config: &ApiConfig) -> String {
+    config
+        .media_url_rewrite
+        .as_ref()
+        .map(|r| r.from.clone())
+        .unwrap_or_default()
+}
+
+fn rewrite_to_of(config: &ApiConfig) -> String {
+    config
+        .media_url_rewrite
+        .as_ref()
+        .map(|r| r.to.clone())
+        .unwrap_or_default()
+}
+
+fn transport_name(kind: TransportKind) -> &'static str {
+    match kind {
+        TransportKind::Rest => "HTTP requests and server-sent events",
+        TransportKind::Ws => "WebSocket",
+    }
+}
+
+fn timeout_of(config: &ApiConfig) -> String {
+    config.request_policy().timeout.as_secs().to_string()
+}
+
+fn retries_of(config: &ApiConfig) -> String {
+    config.request_policy().retries.to_string()
+}
+
+fn concurrency_of(config: &ApiConfig) -> String {
+    config.upload_policy().concurrency.to_string()
+}
+
+fn chunk_size_of(config: &ApiConfig) -> String {
+    (config.upload_policy().chunk_size / 1_000_000.0).to_string()
+}
+
+fn bandwidth_of(config: &ApiConfig) -> String {
+    match config.upload_policy().bandwidth {
+        Some(bandwidth) => (bandwidth / 1000.0).to_string(),
+        None => "no limit".to_string(),
+    }
+}
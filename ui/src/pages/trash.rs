@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use leptos::*;
+
+use crate::{
+    components::dashboard::{use_paged_media, TrashControls},
+    data::{MediaItem, MediaQuery, PAGE_SIZE},
+};
+
+/// Soft-deleted media waiting to be restored or deleted for good
+#[component]
+pub fn Trash() -> impl IntoView {
+    let media = use_context::<ReadSignal<HashMap<String, MediaItem>>>().unwrap();
+    let (has_more, load_more) = use_paged_media(|| MediaQuery {
+        trashed: Some(true),
+        limit: PAGE_SIZE,
+        ..Default::default()
+    });
+    let trashed = move || {
+        let mut items = media.with(|m| {
+            m.values()
+                .filter(|item| item.trashed)
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        items.sort_by(|a, b| a.title.cmp(&b.title));
+        items
+    };
+    view! {
+        <div id="trash" class="content">
+            <h2>"Trash"</h2>
+            <Show
+                when=move || !trashed().is_empty()
+                fallback=|| view! { <p>"The trash is empty."</p> }
+            >
+                <ul>
+                    <For
+                        each=trashed
+                        key=|item| item.id.clone()
+                        children=|item| {
+                            view! {
+                                <li>
+                                    <span class="title">{item.title.clone()}</span>
+                                    <TrashControls id=item.id title=item.title trashed=true/>
+                                </li>
+                            }
+                        }
+                    />
+
+                </ul>
+                <Show when=has_more>
+                    <button on:click=move |_| load_more()>"Load more"</button>
+                </Show>
+            </Show>
+        </div>
+    }
+}
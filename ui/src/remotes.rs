@@ -0,0 +1,197 @@
+//! Media Manager servers the UI knows about, persisted in local storage
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ApiConfig, storage};
+
+const STORAGE_KEY: &str = "media-manager.remotes";
+
+/// Id of the remote served from the same origin as the UI
+pub const DEFAULT_REMOTE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Remote {
+    pub id: String,
+    pub name: String,
+    /// Origin of the server, e.g. `https://nas.local:8080`. The API and
+    /// uploads live under `/api` and `/files`. Empty for the default remote
+    /// to follow the settings page.
+    pub base_url: String,
+    /// Sent to the server as a bearer token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Remote {
+    /// The server configured on the settings page
+    pub fn default_remote() -> Self {
+        Self {
+            id: DEFAULT_REMOTE.to_string(),
+            name: "This server".to_string(),
+            base_url: String::new(),
+            token: None,
+        }
+    }
+
+    /// Whether the remote's URL comes from the settings page
+    pub fn follows_settings(&self) -> bool {
+        self.id == DEFAULT_REMOTE && self.base_url.is_empty()
+    }
+
+    /// Origin of the server
+    pub fn origin(&self, config: &ApiConfig) -> String {
+        if self.follows_settings() {
+            config.base_url()
+        } else {
+            self.base_url.clone()
+        }
+    }
+}
+
+/// Where the library being browsed comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+    /// Fake data, no server required
+    Demo,
+    /// The remote with this id
+    Remote(String),
+    /// Every remote at once
+    Merged,
+}
+
+impl Source {
+    /// Identifies the source's media store
+    pub fn key(&self) -> String {
+        match self {
+            Self::Demo => "demo".to_string(),
+            Self::Remote(id) => format!("remote:{}", id),
+            Self::Merged => "merged".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    remotes: Vec<Remote>,
+    active: Source,
+}
+
+/// Registry of remotes and the source currently browsed
+#[derive(Clone, Copy)]
+pub struct Remotes {
+    list: RwSignal<Vec<Remote>>,
+    active: RwSignal<Source>,
+}
+
+impl Remotes {
+    /// Load the registry from local storage and keep it saved there
+    pub fn new() -> Self {
+        let saved = storage::load(STORAGE_KEY).unwrap_or_else(|| Saved {
+            remotes: vec![Remote::default_remote()],
+            active: if cfg!(feature = "demo") {
+                Source::Demo
+            } else {
+                Source::Remote(DEFAULT_REMOTE.to_string())
+            },
+        });
+        let remotes = Self {
+            list: create_rw_signal(saved.remotes),
+            active: create_rw_signal(saved.active),
+        };
+        create_effect(move |_| {
+            storage::save(
+                STORAGE_KEY,
+                &Saved {
+                    remotes: remotes.list.get(),
+                    active: remotes.active.get(),
+                },
+            )
+        });
+        remotes
+    }
+
+    pub fn list(&self) -> Vec<Remote> {
+        self.list.get()
+    }
+
+    pub fn active(&self) -> Source {
+        self.active.get()
+    }
+
+    pub fn set_active(&self, source: Source) {
+        self.active.set(source)
+    }
+
+    /// The active source along with the remotes it reads from
+    pub fn selection(&self) -> (Source, Vec<Remote>) {
+        let source = self.active.get();
+        let remotes = self.list.with(|list| match &source {
+            Source::Demo => Vec::new(),
+            Source::Remote(id) => list.iter().filter(|r| &r.id == id).cloned().collect(),
+            Source::Merged => list.clone(),
+        });
+        (source, remotes)
+    }
+
+    /// Remote new uploads go to: the one browsed, or the first one when
+    /// browsing them all. None for the demo.
+    pub fn upload_target(&self) -> Option<Remote> {
+        let (source, remotes) = untrack(|| self.selection());
+        match source {
+            Source::Demo => None,
+            // like the client, fall back to the default remote
+            _ => Some(
+                remotes
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(Remote::default_remote),
+            ),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Remote> {
+        self.list
+            .with_untracked(|list| list.iter().find(|r| r.id == id).cloned())
+    }
+
+    pub fn add(&self, name: String, base_url: String, token: Option<String>) {
+        let id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        self.list.update(|list| {
+            list.push(Remote {
+                id,
+                name,
+                base_url: base_url.trim_end_matches('/').to_string(),
+                token,
+            })
+        })
+    }
+
+    pub fn update(&self, remote: Remote) {
+        self.list.update(|list| {
+            if let Some(r) = list.iter_mut().find(|r| r.id == remote.id) {
+                *r = remote
+            }
+        })
+    }
+
+    /// Forget remote `id`, switching to the first remaining remote if it
+    /// was being browsed
+    pub fn remove(&self, id: &str) {
+        self.list.update(|list| list.retain(|r| r.id != id));
+        if self.active.get_untracked() == Source::Remote(id.to_string()) {
+            self.active.set(
+                match self.list.with_untracked(|list| list.first().cloned()) {
+                    Some(r) => Source::Remote(r.id),
+                    None => Source::Demo,
+                },
+            )
+        }
+    }
+}
+
+impl Default for Remotes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,36 @@
+//! Settings and state kept in the browser's local storage, as JSON
+
+use leptos::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::log;
+
+fn storage() -> Option<web_sys::Storage> {
+    window().local_storage().ok().flatten()
+}
+
+/// What was saved under `key`, if anything could be read back
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let json = storage()?.get_item(key).ok()??;
+    match serde_json::from_str(&json) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log!("ignoring saved {}: {}", key, e);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize + ?Sized>(key: &str, value: &T) {
+    let Some(storage) = storage() else {
+        return;
+    };
+    match serde_json::to_string(value) {
+        Ok(json) => {
+            if let Err(e) = storage.set_item(key, &json) {
+                log!("failed to save {}: {:?}", key, e)
+            }
+        }
+        Err(e) => log!("failed to save {}: {}", key, e),
+    }
+}